@@ -0,0 +1,280 @@
+use crate::svg::{SvgConfig, Theme};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE: &str = "eml.toml";
+
+// layout values from the command line or the [layout] table of eml.toml,
+// anything left unset falls back to the next source
+#[derive(clap::Args, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutArgs {
+    /// space around and between cards
+    #[clap(long, value_parser)]
+    pub pad: Option<f64>,
+
+    /// minimum card width
+    #[clap(long, value_parser)]
+    pub card_width: Option<f64>,
+
+    /// minimum card height
+    #[clap(long, value_parser)]
+    pub card_height: Option<f64>,
+
+    /// width at which card text wraps or truncates
+    #[clap(long, value_parser)]
+    pub max_card_width: Option<f64>,
+
+    /// minimum height of the form and job lane
+    #[clap(long, value_parser)]
+    pub top_lane_height: Option<f64>,
+
+    /// minimum height of the command and view lane
+    #[clap(long, value_parser)]
+    pub middle_lane_height: Option<f64>,
+
+    /// minimum height of the event lane
+    #[clap(long, value_parser)]
+    pub bottom_lane_height: Option<f64>,
+
+    /// arrowhead length
+    #[clap(long, value_parser)]
+    pub arrowhead: Option<f64>,
+
+    /// space between a card's outline and its text [default: pad / 12]
+    #[clap(long, value_parser)]
+    pub text_inset: Option<f64>,
+}
+
+impl LayoutArgs {
+    // values set here win over those set in other
+    pub fn or(self, other: LayoutArgs) -> LayoutArgs {
+        LayoutArgs {
+            pad: self.pad.or(other.pad),
+            card_width: self.card_width.or(other.card_width),
+            card_height: self.card_height.or(other.card_height),
+            max_card_width: self.max_card_width.or(other.max_card_width),
+            top_lane_height: self.top_lane_height.or(other.top_lane_height),
+            middle_lane_height: self.middle_lane_height.or(other.middle_lane_height),
+            bottom_lane_height: self.bottom_lane_height.or(other.bottom_lane_height),
+            arrowhead: self.arrowhead.or(other.arrowhead),
+            text_inset: self.text_inset.or(other.text_inset),
+        }
+    }
+
+    // every value is a length, anything but a positive number breaks the
+    // geometry
+    pub fn resolve(&self) -> Result<SvgConfig, String> {
+        let default = SvgConfig::default();
+        let length = |name: &str, value: Option<f64>, default: f64| match value {
+            Some(v) if !(v.is_finite() && v > 0f64) => Err(format!(
+                "layout {} must be a positive number, not {}",
+                name, v
+            )),
+            v => Ok(v.unwrap_or(default)),
+        };
+        let pad = length("pad", self.pad, default.pad)?;
+        Ok(SvgConfig {
+            pad,
+            card_width: length("card_width", self.card_width, default.card_width)?,
+            card_height: length("card_height", self.card_height, default.card_height)?,
+            max_card_width: length(
+                "max_card_width",
+                self.max_card_width,
+                default.max_card_width,
+            )?,
+            top_lane_height: length(
+                "top_lane_height",
+                self.top_lane_height,
+                default.top_lane_height,
+            )?,
+            middle_lane_height: length(
+                "middle_lane_height",
+                self.middle_lane_height,
+                default.middle_lane_height,
+            )?,
+            bottom_lane_height: length(
+                "bottom_lane_height",
+                self.bottom_lane_height,
+                default.bottom_lane_height,
+            )?,
+            arrowhead: length("arrowhead", self.arrowhead, default.arrowhead)?,
+            text_inset: length("text_inset", self.text_inset, pad / 12f64)?,
+        })
+    }
+}
+
+// the contents of an eml.toml project config
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub theme: Option<String>,
+    pub layout: LayoutArgs,
+    // the file the config was read from
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl ProjectConfig {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let config =
+            ProjectConfig::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(ProjectConfig {
+            path: Some(path.to_path_buf()),
+            ..config
+        })
+    }
+
+    // the explicit path if given, otherwise the nearest eml.toml in the
+    // working directory or one of its parents, otherwise nothing
+    pub fn discover(path: Option<&Path>) -> Result<Self, String> {
+        if let Some(path) = path {
+            return ProjectConfig::load(path);
+        }
+        let cwd = env::current_dir().map_err(|e| e.to_string())?;
+        ProjectConfig::search(&cwd)
+    }
+
+    fn search(dir: &Path) -> Result<Self, String> {
+        match find(dir) {
+            Some(path) => ProjectConfig::load(&path),
+            None => Ok(ProjectConfig::default()),
+        }
+    }
+
+    // a theme file is relative to the config that names it, not to the
+    // directory eml runs in
+    pub fn theme(&self) -> Option<String> {
+        let theme = self.theme.as_ref()?;
+        let dir = self.path.as_ref().and_then(|p| p.parent());
+        match dir {
+            Some(dir) if Theme::builtin(theme).is_none() && Path::new(theme).is_relative() => {
+                Some(dir.join(theme).to_string_lossy().to_string())
+            }
+            _ => Some(theme.clone()),
+        }
+    }
+}
+
+fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(CONFIG_FILE))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_project_config() {
+        let observed = ProjectConfig::parse(
+            r#"
+            theme = "dark"
+
+            [layout]
+            pad = 100.0
+            card_width = 250.0
+            "#,
+        )
+        .unwrap();
+        let expected = ProjectConfig {
+            theme: Some("dark".to_string()),
+            layout: LayoutArgs {
+                pad: Some(100f64),
+                card_width: Some(250f64),
+                ..LayoutArgs::default()
+            },
+            path: None,
+        };
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_unknown_layout_key() {
+        assert!(ProjectConfig::parse("[layout]\npadding = 10.0").is_err());
+    }
+
+    #[test]
+    fn test_cli_takes_precedence() {
+        let cli = LayoutArgs {
+            pad: Some(60f64),
+            ..LayoutArgs::default()
+        };
+        let file = LayoutArgs {
+            pad: Some(100f64),
+            card_width: Some(250f64),
+            ..LayoutArgs::default()
+        };
+        let observed = cli.or(file).resolve().unwrap();
+        assert_eq!(observed.pad, 60f64);
+        assert_eq!(observed.card_width, 250f64);
+        assert_eq!(observed.card_height, 150f64);
+        assert_eq!(observed.text_inset, 5f64);
+    }
+
+    #[test]
+    fn test_default_layout() {
+        let observed = LayoutArgs::default().resolve().unwrap();
+        let expected = SvgConfig::default();
+        assert_eq!(observed.pad, expected.pad);
+        assert_eq!(observed.text_inset, expected.text_inset);
+        assert_eq!(observed.arrowhead, expected.arrowhead);
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        for value in [0f64, -150f64, f64::NAN, f64::INFINITY] {
+            let layout = LayoutArgs {
+                card_height: Some(value),
+                ..LayoutArgs::default()
+            };
+            let observed = layout.resolve().unwrap_err();
+            let expected = format!(
+                "layout card_height must be a positive number, not {}",
+                value
+            );
+            assert_eq!(expected, observed);
+        }
+        let file = ProjectConfig::parse("[layout]\npad = -1.0").unwrap();
+        assert!(LayoutArgs::default().or(file.layout).resolve().is_err());
+    }
+
+    #[test]
+    fn test_find_in_parent() {
+        let root = env::temp_dir().join(format!("eml-config-{}", std::process::id()));
+        let nested = root.join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(CONFIG_FILE), "theme = \"monochrome\"").unwrap();
+        let observed = find(&nested);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(observed, Some(root.join(CONFIG_FILE)));
+    }
+
+    #[test]
+    fn test_theme_relative_to_config() {
+        let root = env::temp_dir().join(format!("eml-theme-{}", std::process::id()));
+        let nested = root.join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(root.join("themes")).unwrap();
+        fs::write(root.join(CONFIG_FILE), "theme = \"themes/brand.toml\"").unwrap();
+        fs::write(root.join("themes").join("brand.toml"), "base = \"dark\"").unwrap();
+        let project = ProjectConfig::search(&nested).unwrap();
+        let theme = Theme::load(&project.theme().unwrap());
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(theme.unwrap(), Theme::dark());
+        let builtin = ProjectConfig {
+            theme: Some("dark".to_string()),
+            path: Some(root.join(CONFIG_FILE)),
+            ..ProjectConfig::default()
+        };
+        assert_eq!(builtin.theme(), Some("dark".to_string()));
+    }
+}
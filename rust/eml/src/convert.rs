@@ -0,0 +1,197 @@
+use crate::eventmodel::emlyml::to_emlyml;
+use crate::eventmodel::*;
+use crate::print::print;
+use crate::utils::flow_id;
+use std::path::Path;
+
+// the model languages eml convert translates between
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    Eml,
+    Emlyml,
+}
+
+impl Language {
+    pub fn from_path(path: &str) -> Option<Language> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "eml" => Some(Language::Eml),
+            "yaml" | "yml" => Some(Language::Emlyml),
+            _ => None,
+        }
+    }
+}
+
+fn card_id(expr: &Expression) -> Option<&ExpressionId> {
+    match expr {
+        Expression::Form(id, _)
+        | Expression::Job(id, _)
+        | Expression::Command(id, _)
+        | Expression::Event(id, _)
+        | Expression::View(id, _) => Some(id),
+        _ => None,
+    }
+}
+
+fn with_id(expr: Expression, id: ExpressionId) -> Expression {
+    match expr {
+        Expression::Form(_, body) => Expression::Form(id, body),
+        Expression::Job(_, body) => Expression::Job(id, body),
+        Expression::Command(_, body) => Expression::Command(id, body),
+        Expression::Event(_, body) => Expression::Event(id, body),
+        Expression::View(_, body) => Expression::View(id, body),
+        other => other,
+    }
+}
+
+// undo what emlyml cannot express: arrows that continue the previous one are
+// joined into a chain, and cards with qualified ids go back into their
+// context, the first one of that name
+fn regroup(expressions: Vec<Expression>) -> Vec<Expression> {
+    let mut out: Vec<Expression> = Vec::with_capacity(expressions.len());
+    for expr in expressions {
+        if let Expression::Flow(_, ids) = &expr {
+            if let Some(Expression::Flow(id, chain)) = out.last_mut() {
+                if chain.last() == ids.first() {
+                    chain.extend(ids.iter().skip(1).cloned());
+                    *id = ExpressionId(flow_id(chain.iter().map(|s| s.0.as_str())));
+                    continue;
+                }
+            }
+            out.push(expr);
+            continue;
+        }
+        let qualified = card_id(&expr).and_then(|id| id.0.rsplit_once('.'));
+        let (context, bare) = match qualified {
+            Some((context, bare)) => (context.to_string(), bare.to_string()),
+            None => {
+                out.push(expr);
+                continue;
+            }
+        };
+        let card = with_id(expr, ExpressionId(bare));
+        let existing = out.iter_mut().find_map(|e| match e {
+            Expression::Context(id, exprs) if id.0 == context => Some(exprs),
+            _ => None,
+        });
+        match existing {
+            Some(exprs) => exprs.push(card),
+            None => out.push(Expression::Context(ExpressionId(context), vec![card])),
+        }
+    }
+    out
+}
+
+pub fn convert(model: EventModel, to: Language) -> Result<String, String> {
+    match to {
+        Language::Eml => print(&EventModel {
+            expressions: regroup(model.expressions),
+        }),
+        Language::Emlyml => to_emlyml(&model),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventmodel::emlyml::from_emlyml;
+    use crate::parse::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_language_from_path() {
+        assert_eq!(Language::from_path("model.eml"), Some(Language::Eml));
+        assert_eq!(Language::from_path("model.YML"), Some(Language::Emlyml));
+        assert_eq!(Language::from_path("model.yaml"), Some(Language::Emlyml));
+        assert_eq!(Language::from_path("-"), None);
+    }
+
+    #[test]
+    fn test_convert_to_eml() {
+        let input = indoc! {"
+            - Form: {id: TodoForm, text: '+ Title: milk'}
+            - Command: {id: Todos.AddTodo}
+            - Event: {id: Todos.TodoAdded}
+            - =>: {begin_at: TodoForm, end_at: Todos.AddTodo}
+            - =>: {begin_at: Todos.AddTodo, end_at: Todos.TodoAdded}
+            - View: {id: Lists}
+            - =>: {begin_at: Todos.TodoAdded, end_at: Lists}
+        "};
+        let expected = indoc! {"
+            # eml: 0.0.1
+
+            form TodoForm {
+              Title: milk
+            }
+
+            context Todos {
+              command AddTodo {}
+              event TodoAdded {}
+            }
+
+            flow { TodoForm => Todos.AddTodo => Todos.TodoAdded }
+            view Lists {}
+            flow { Todos.TodoAdded => Lists }
+        "};
+        let observed = convert(from_emlyml(input).unwrap(), Language::Eml).unwrap();
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = indoc! {"
+            # eml: 0.0.1
+
+            form CustomerForm {
+              Name: John
+              Age: 21
+            }
+
+            command AddCustomer {
+              Name: John
+              Age: 21
+            }
+
+            context Billing {
+              event CustomerAdded {
+                Name: John
+              }
+
+              view Accounts {
+                | CustomerId | State |
+                | 123        | DONE  |
+              }
+            }
+
+            flow { CustomerForm => AddCustomer => Billing.CustomerAdded => Billing.Accounts }
+        "};
+        let yaml = convert(parse(input).unwrap(), Language::Emlyml).unwrap();
+        let observed = convert(from_emlyml(&yaml).unwrap(), Language::Eml).unwrap();
+        assert_eq!(input, observed);
+        // flows between a context's cards do not split the context
+        let input = indoc! {"
+            # eml: 0.0.1
+            context Billing {
+              command A {}
+              event B { use A }
+              flow { A => B }
+              view C {}
+              flow { B => C }
+            }
+        "};
+        let expected = indoc! {"
+            # eml: 0.0.1
+
+            context Billing {
+              command A {}
+              event B {}
+              view C {}
+            }
+
+            flow { Billing.A => Billing.B => Billing.C }
+        "};
+        let yaml = convert(parse(input).unwrap(), Language::Emlyml).unwrap();
+        let observed = convert(from_emlyml(&yaml).unwrap(), Language::Eml).unwrap();
+        assert_eq!(expected, observed);
+    }
+}
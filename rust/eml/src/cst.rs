@@ -0,0 +1,612 @@
+use std::ops::Range;
+
+// a lossless syntax tree: every byte of the source is in exactly one token,
+// whitespace, line breaks and comments included, so the tree can be edited
+// and written back without losing anything
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyntaxKind {
+    // trivia
+    Whitespace,
+    Newline,
+    Comment,
+    // tokens
+    Header,
+    Keyword,
+    Ident,
+    Dot,
+    Colon,
+    Comma,
+    Arrow,
+    LBrace,
+    RBrace,
+    FieldValue,
+    Raw,
+    Error,
+    // nodes
+    File,
+    Card,
+    Flow,
+    Context,
+    Reference,
+    Fields,
+    Field,
+    Table,
+    Use,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub kind: SyntaxKind,
+    pub text: String,
+    // byte offset in the source the tree was parsed from
+    pub offset: usize,
+}
+
+impl Token {
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.text.len()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub kind: SyntaxKind,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: SyntaxKind, children: Vec<Element>) -> Self {
+        Node { kind, children }
+    }
+
+    // all tokens below this node in source order
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        for child in self.children.iter() {
+            match child {
+                Element::Node(node) => tokens.extend(node.tokens()),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    pub fn tokens_mut(&mut self) -> Vec<&mut Token> {
+        let mut tokens = Vec::new();
+        for child in self.children.iter_mut() {
+            match child {
+                Element::Node(node) => tokens.extend(node.tokens_mut()),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    pub fn text(&self) -> String {
+        self.tokens().iter().map(|t| t.text.as_str()).collect()
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.offset..last.span().end,
+            _ => 0..0,
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    // the first direct child token of a kind
+    pub fn token(&self, kind: SyntaxKind) -> Option<&Token> {
+        self.children.iter().find_map(|child| match child {
+            Element::Token(token) if token.kind == kind => Some(token),
+            _ => None,
+        })
+    }
+
+    pub fn node(&self, kind: SyntaxKind) -> Option<&Node> {
+        self.nodes().find(|node| node.kind == kind)
+    }
+}
+
+// a backtracking parser over the source, mirroring the eml grammar. a rule
+// either succeeds and adds its elements, or fails and leaves the position
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+const KEYWORDS: [&str; 7] = ["form", "job", "command", "event", "view", "flow", "context"];
+
+fn is_space(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn take(&mut self, kind: SyntaxKind, len: usize) -> Element {
+        let token = Token {
+            kind,
+            text: self.rest()[..len].to_string(),
+            offset: self.pos,
+        };
+        self.pos += len;
+        Element::Token(token)
+    }
+
+    fn length(&self, f: impl Fn(char) -> bool) -> usize {
+        self.rest()
+            .char_indices()
+            .find(|(_, c)| !f(*c))
+            .map_or(self.rest().len(), |(i, _)| i)
+    }
+
+    fn tag(&mut self, kind: SyntaxKind, tag: &str, out: &mut Vec<Element>) -> Option<()> {
+        if !self.rest().starts_with(tag) {
+            return None;
+        }
+        out.push(self.take(kind, tag.len()));
+        Some(())
+    }
+
+    fn space0(&mut self, out: &mut Vec<Element>) {
+        let len = self.length(is_space);
+        if len > 0 {
+            out.push(self.take(SyntaxKind::Whitespace, len));
+        }
+    }
+
+    fn space1(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        let len = self.length(is_space);
+        if len == 0 {
+            return None;
+        }
+        out.push(self.take(SyntaxKind::Whitespace, len));
+        Some(())
+    }
+
+    fn newline(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.tag(SyntaxKind::Newline, "\r\n", out)
+            .or_else(|| self.tag(SyntaxKind::Newline, "\n", out))
+    }
+
+    // spaces, tabs, carriage returns and line breaks
+    fn multispace0(&mut self, out: &mut Vec<Element>) -> bool {
+        let start = self.pos;
+        loop {
+            let len = self.length(|c| is_space(c) || c == '\r');
+            let crlf = self.rest()[len..].starts_with('\n')
+                && len > 0
+                && self.rest()[..len].ends_with('\r');
+            if crlf {
+                if len > 1 {
+                    out.push(self.take(SyntaxKind::Whitespace, len - 1));
+                }
+                out.push(self.take(SyntaxKind::Newline, 2));
+            } else if len > 0 {
+                out.push(self.take(SyntaxKind::Whitespace, len));
+            } else if self.newline(out).is_none() {
+                return self.pos > start;
+            }
+        }
+    }
+
+    fn word(&mut self, kind: SyntaxKind, out: &mut Vec<Element>) -> Option<()> {
+        let len = self.length(|c| c.is_ascii_alphabetic());
+        if len == 0 {
+            return None;
+        }
+        out.push(self.take(kind, len));
+        Some(())
+    }
+
+    // run a rule, restoring the position and dropping its output on failure
+    fn attempt<T>(
+        &mut self,
+        out: &mut Vec<Element>,
+        rule: impl FnOnce(&mut Self, &mut Vec<Element>) -> Option<T>,
+    ) -> Option<T> {
+        let start = self.pos;
+        let mut elements = Vec::new();
+        match rule(self, &mut elements) {
+            Some(value) => {
+                out.append(&mut elements);
+                Some(value)
+            }
+            None => {
+                self.pos = start;
+                None
+            }
+        }
+    }
+
+    fn node(
+        &mut self,
+        kind: SyntaxKind,
+        out: &mut Vec<Element>,
+        rule: impl FnOnce(&mut Self, &mut Vec<Element>) -> Option<()>,
+    ) -> Option<()> {
+        let mut children = Vec::new();
+        self.attempt(&mut children, rule)?;
+        out.push(Element::Node(Node::new(kind, children)));
+        Some(())
+    }
+
+    // # eml: 0.0.1
+    fn header(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        let rest = self.rest();
+        let after = rest.strip_prefix('#')?.trim_start_matches(is_space);
+        let after = after.strip_prefix("eml:")?.trim_start_matches(is_space);
+        let mut numbers = after;
+        for i in 0..3 {
+            let digits = numbers.len()
+                - numbers
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            if digits == 0 {
+                return None;
+            }
+            numbers = &numbers[digits..];
+            if i < 2 {
+                numbers = numbers.strip_prefix('.')?;
+            }
+        }
+        out.push(self.take(SyntaxKind::Header, rest.len() - numbers.len()));
+        Some(())
+    }
+
+    // a comment runs to the end of its line and takes the line break with it
+    fn comment(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        if !self.rest().starts_with('#') {
+            return None;
+        }
+        self.attempt(out, |p, out| {
+            let len = p.length(|c| c != '\r' && c != '\n');
+            out.push(p.take(SyntaxKind::Comment, len));
+            match p.rest().is_empty() {
+                true => Some(()),
+                false => p.newline(out),
+            }
+        })?;
+        Some(())
+    }
+
+    fn reference(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Reference, out, |p, out| {
+            p.word(SyntaxKind::Ident, out)?;
+            while p
+                .attempt(out, |p, out| {
+                    p.tag(SyntaxKind::Dot, ".", out)?;
+                    p.word(SyntaxKind::Ident, out)
+                })
+                .is_some()
+            {}
+            Some(())
+        })
+    }
+
+    // { use Other }
+    fn use_block(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Use, out, |p, out| {
+            p.tag(SyntaxKind::LBrace, "{", out)?;
+            p.space0(out);
+            p.tag(SyntaxKind::Keyword, "use", out)?;
+            p.tag(SyntaxKind::Whitespace, " ", out)?;
+            p.reference(out)?;
+            p.space0(out);
+            p.tag(SyntaxKind::RBrace, "}", out)
+        })
+    }
+
+    // name: value, running to the next comma, line break or brace
+    fn field(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Field, out, |p, out| {
+            p.space0(out);
+            p.word(SyntaxKind::Ident, out)?;
+            p.tag(SyntaxKind::Colon, ":", out)?;
+            p.space0(out);
+            let len = p.length(|c| c != ',' && c != '\n' && c != '}');
+            if len == 0 {
+                return None;
+            }
+            out.push(p.take(SyntaxKind::FieldValue, len));
+            Some(())
+        })
+    }
+
+    // { name: value, name: value } or one field per line
+    fn fields_block(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Fields, out, |p, out| {
+            p.tag(SyntaxKind::LBrace, "{", out)?;
+            if p.newline(out).is_none() {
+                p.space0(out);
+            }
+            if p.field(out).is_some() {
+                while p
+                    .attempt(out, |p, out| {
+                        p.tag(SyntaxKind::Comma, ",", out)
+                            .or_else(|| p.newline(out))?;
+                        p.field(out)
+                    })
+                    .is_some()
+                {}
+            }
+            p.multispace0(out);
+            p.tag(SyntaxKind::RBrace, "}", out)
+        })
+    }
+
+    // anything up to the closing brace, kept line by line
+    fn raw_block(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Table, out, |p, out| {
+            p.tag(SyntaxKind::LBrace, "{", out)?;
+            p.space0(out);
+            let end = p.pos + p.rest().find('}')?;
+            while p.pos < end {
+                let line = &p.source[p.pos..end];
+                let len = line.find('\n').unwrap_or(line.len());
+                let indent = line[..len].len() - line[..len].trim_start().len();
+                if indent > 0 {
+                    out.push(p.take(SyntaxKind::Whitespace, indent));
+                }
+                if len > indent {
+                    out.push(p.take(SyntaxKind::Raw, len - indent));
+                }
+                if p.pos < end {
+                    out.push(p.take(SyntaxKind::Newline, 1));
+                }
+            }
+            p.tag(SyntaxKind::RBrace, "}", out)
+        })
+    }
+
+    fn keyword(&mut self, out: &mut Vec<Element>) -> Option<&'static str> {
+        let keyword = KEYWORDS.iter().find(|k| self.rest().starts_with(*k))?;
+        out.push(self.take(SyntaxKind::Keyword, keyword.len()));
+        Some(keyword)
+    }
+
+    fn card(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Card, out, |p, out| {
+            match p.keyword(out)? {
+                "flow" | "context" => return None,
+                _ => {}
+            }
+            p.space1(out)?;
+            p.word(SyntaxKind::Ident, out)?;
+            p.space1(out)?;
+            p.use_block(out)
+                .or_else(|| p.fields_block(out))
+                .or_else(|| p.raw_block(out))
+        })
+    }
+
+    // flow { A => B => Context.C }
+    fn flow(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Flow, out, |p, out| {
+            p.tag(SyntaxKind::Keyword, "flow", out)?;
+            p.space0(out);
+            p.tag(SyntaxKind::LBrace, "{", out)?;
+            p.space0(out);
+            let step = |p: &mut Self, out: &mut Vec<Element>| {
+                p.space0(out);
+                p.reference(out)
+            };
+            if p.attempt(out, step).is_some() {
+                while p
+                    .attempt(out, |p, out| {
+                        p.space0(out);
+                        p.tag(SyntaxKind::Arrow, "=>", out)?;
+                        step(p, out)
+                    })
+                    .is_some()
+                {}
+            }
+            p.space0(out);
+            p.tag(SyntaxKind::RBrace, "}", out)
+        })
+    }
+
+    // contexts group expressions. a nested context parses so that the model
+    // can say it is not allowed
+    fn context(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.node(SyntaxKind::Context, out, |p, out| {
+            p.tag(SyntaxKind::Keyword, "context", out)?;
+            p.space1(out)?;
+            p.word(SyntaxKind::Ident, out)?;
+            p.space1(out)?;
+            p.tag(SyntaxKind::LBrace, "{", out)?;
+            p.separated(
+                out,
+                |p, out| p.multispace0(out).then_some(()),
+                |p, out| p.expression(out),
+            );
+            p.tag(SyntaxKind::RBrace, "}", out)
+        })
+    }
+
+    fn expression(&mut self, out: &mut Vec<Element>) -> Option<()> {
+        self.card(out)
+            .or_else(|| self.flow(out))
+            .or_else(|| self.context(out))
+    }
+
+    // items with comments and gaps between them, where a gap is required
+    // between two items
+    fn separated(
+        &mut self,
+        out: &mut Vec<Element>,
+        gap: impl Fn(&mut Self, &mut Vec<Element>) -> Option<()>,
+        item: impl Fn(&mut Self, &mut Vec<Element>) -> Option<()>,
+    ) {
+        let mut open = true;
+        loop {
+            if self.comment(out).is_some() || self.attempt(out, &gap).is_some() {
+                open = true;
+            } else if open && self.attempt(out, &item).is_some() {
+                open = false;
+            } else {
+                return;
+            }
+        }
+    }
+
+    // the length up to the next line that starts an expression or a comment
+    fn resync(&self) -> usize {
+        let rest = self.rest();
+        let mut end = 0;
+        while let Some(i) = rest[end..].find('\n') {
+            end += i + 1;
+            let line = &rest[end..];
+            if line.starts_with('#') || KEYWORDS.iter().any(|k| line.starts_with(k)) {
+                return end;
+            }
+        }
+        rest.len()
+    }
+
+    fn file(&mut self) -> Node {
+        let mut out = Vec::new();
+        self.header(&mut out);
+        loop {
+            self.separated(
+                &mut out,
+                |p, out| {
+                    p.space0(out);
+                    p.newline(out)
+                },
+                |p, out| p.expression(out),
+            );
+            self.multispace0(&mut out);
+            if self.rest().is_empty() {
+                break;
+            }
+            // trailing whitespace is fine, anything else did not parse and
+            // the parser picks up again at the next top level line
+            let (kind, len) = match self.rest().trim().is_empty() {
+                true => (SyntaxKind::Whitespace, self.rest().len()),
+                false => (SyntaxKind::Error, self.resync()),
+            };
+            out.push(self.take(kind, len));
+        }
+        Node::new(SyntaxKind::File, out)
+    }
+}
+
+// parsing never fails: input the grammar does not accept ends up in error
+// tokens, each running to the next line that starts an expression
+pub fn parse(source: &str) -> Node {
+    Parser { source, pos: 0 }.file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const MODEL: &str = indoc! {"
+        # eml: 0.0.1
+
+        # Todos
+        form TodoForm { title: milk, due:today }
+        command AddTodo { use TodoForm }
+        context Lists {
+          # the list
+          view Todos {
+            | title |
+            | milk  |
+          }
+          flow { AddTodo => Todos }
+        }
+        flow { TodoForm => AddTodo =>Lists.Todos }
+    "};
+
+    #[test]
+    fn test_lossless() {
+        let inputs = [
+            MODEL,
+            "",
+            "# eml: 0.0.1",
+            "# eml: 0.0.1\r\nform A {\r\n  a: b\r\n}\r\n",
+            "# eml: 0.0.1\nform A { a: b\nevent { ??? \n\n",
+            "no header\ncommand A {}\n",
+            "# eml: 0.0.1\ncontext A {\n  context B {}\n}\n",
+        ];
+        for input in inputs {
+            assert_eq!(parse(input).text(), input);
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let file = parse(MODEL);
+        for token in file.tokens() {
+            assert_eq!(
+                &MODEL[token.offset..token.offset + token.text.len()],
+                token.text
+            );
+        }
+        let flow = file
+            .nodes()
+            .filter(|n| n.kind == SyntaxKind::Flow)
+            .last()
+            .unwrap();
+        let steps = flow
+            .nodes()
+            .map(|n| (n.text(), n.tokens()[0].offset))
+            .collect::<Vec<(String, usize)>>();
+        let offset = MODEL.rfind("Lists.Todos").unwrap();
+        assert_eq!(steps[2], ("Lists.Todos".to_string(), offset));
+    }
+
+    #[test]
+    fn test_structure() {
+        let file = parse(MODEL);
+        let kinds = file.nodes().map(|n| n.kind).collect::<Vec<SyntaxKind>>();
+        let expected = vec![
+            SyntaxKind::Card,
+            SyntaxKind::Card,
+            SyntaxKind::Context,
+            SyntaxKind::Flow,
+        ];
+        assert_eq!(expected, kinds);
+        let comments = file
+            .tokens()
+            .into_iter()
+            .filter(|t| t.kind == SyntaxKind::Comment)
+            .map(|t| t.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(comments, vec!["# Todos", "# the list"]);
+        assert_eq!(file.token(SyntaxKind::Header).unwrap().text, "# eml: 0.0.1");
+        assert!(file.token(SyntaxKind::Error).is_none());
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let input = "# eml: 0.0.1\nform A {}\n  event B { oops\n  }\ncommand C {}\n";
+        let file = parse(input);
+        let error = file.token(SyntaxKind::Error).unwrap();
+        assert_eq!(error.text, "event B { oops\n  }\n");
+        assert_eq!(error.offset, input.find("event").unwrap());
+        let ids = file
+            .nodes()
+            .map(|n| n.token(SyntaxKind::Ident).unwrap().text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["A", "C"]);
+    }
+}
@@ -0,0 +1,327 @@
+use super::*;
+use crate::utils::flow_id;
+use serde_yaml::{Mapping, Value};
+
+// the yaml models of the python emlyml tool: a list of single key mappings,
+// either a card type with { id, text } or an arrow with { begin_at, end_at }
+
+#[derive(Deserialize)]
+struct Card {
+    id: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct Arrow {
+    begin_at: String,
+    end_at: String,
+}
+
+// a yaml document starts with --- or its first list entry, eml with its header
+pub fn is_emlyml(input: &str) -> bool {
+    input
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .is_some_and(|line| line.starts_with('-'))
+}
+
+// "+ Name: Bob" lines become fields, anything else is kept as it was written
+fn body(text: &str) -> Body {
+    let lines = text
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>();
+    let fields = lines
+        .iter()
+        .map(|line| {
+            let line = line.trim_start();
+            let line = ["+ ", "- ", "* "]
+                .iter()
+                .find_map(|bullet| line.strip_prefix(bullet))
+                .unwrap_or(line);
+            let (name, data) = line.split_once(':')?;
+            Some(Field::Text(TextField {
+                name: name.trim().to_string(),
+                data: data.trim().to_string(),
+            }))
+        })
+        .collect::<Option<Vec<Field>>>();
+    match fields {
+        Some(fields) => Body::FieldBody(fields),
+        None => Body::TableBody(lines.iter().map(|l| l.to_string()).collect()),
+    }
+}
+
+fn entry(mapping: &Mapping) -> Result<(String, Value), String> {
+    let mut pairs = mapping.iter();
+    match (pairs.next(), pairs.next()) {
+        (Some((Value::String(key), value)), None) => Ok((key.to_lowercase(), value.clone())),
+        _ => Err("expected a single key such as Form or =>".to_string()),
+    }
+}
+
+pub fn from_emlyml(input: &str) -> Result<EventModel, String> {
+    let entries: Vec<Mapping> =
+        serde_yaml::from_str(input).map_err(|e| format!("emlyml model: {}", e))?;
+    let mut expressions = Vec::with_capacity(entries.len());
+    let mut ids: Vec<String> = Vec::new();
+    for (index, mapping) in entries.iter().enumerate() {
+        let error = |e: String| format!("emlyml model: entry {}: {}", index + 1, e);
+        let (key, value) = entry(mapping).map_err(error)?;
+        let expression = match key.as_str() {
+            "form" | "job" | "command" | "event" | "view" => {
+                let card: Card = serde_yaml::from_value(value).map_err(|e| error(e.to_string()))?;
+                let id = ExpressionId(card.id.clone());
+                let body = body(&card.text);
+                ids.push(card.id);
+                match key.as_str() {
+                    "form" => Expression::Form(id, body),
+                    "job" => Expression::Job(id, body),
+                    "command" => Expression::Command(id, body),
+                    "event" => Expression::Event(id, body),
+                    _ => Expression::View(id, body),
+                }
+            }
+            "=>" | "arrow" => {
+                let arrow: Arrow =
+                    serde_yaml::from_value(value).map_err(|e| error(e.to_string()))?;
+                // like emlyml, an arrow may only point between cards above it
+                for id in [&arrow.begin_at, &arrow.end_at] {
+                    if !ids.contains(id) {
+                        return Err(error(format!("unknown card {}", id)));
+                    }
+                }
+                let steps = vec![ExpressionId(arrow.begin_at), ExpressionId(arrow.end_at)];
+                let id = ExpressionId(flow_id(steps.iter().map(|s| s.0.as_str())));
+                Expression::Flow(id, steps)
+            }
+            other => return Err(error(format!("unknown entry {}", other))),
+        };
+        expressions.push(expression);
+    }
+    Ok(EventModel { expressions })
+}
+
+fn type_key(expression_type: ExpressionType) -> &'static str {
+    match expression_type {
+        ExpressionType::Form => "Form",
+        ExpressionType::Job => "Job",
+        ExpressionType::Command => "Command",
+        ExpressionType::Event => "Event",
+        ExpressionType::View => "View",
+        ExpressionType::Flow => "=>",
+        ExpressionType::Context => "Context",
+    }
+}
+
+// walks the expressions in model order, taking resolved cards and flows from
+// the flattened model, which visits them in the same order
+fn write(
+    expressions: &[Expression],
+    flat: &FlatModel,
+    next: &mut (usize, usize),
+    out: &mut String,
+) {
+    for expr in expressions {
+        match expr {
+            Expression::Flow(_, ids) => {
+                for (begin_at, end_at) in
+                    flat.flows[next.1..next.1 + ids.len().saturating_sub(1)].iter()
+                {
+                    out.push_str(&format!(
+                        "- =>: {{begin_at: {}, end_at: {}}}\n",
+                        begin_at, end_at
+                    ));
+                }
+                next.1 += ids.len().saturating_sub(1);
+            }
+            Expression::Context(_, exprs) => write(exprs, flat, next, out),
+            Expression::Comment(text) => {
+                out.push_str(&format!("#{}\n", text));
+            }
+            _ => {
+                let card = &flat.cards[next.0];
+                next.0 += 1;
+                // a blank line between cards, none under a comment
+                let commented = out.lines().last().is_some_and(|l| l.starts_with('#'));
+                if !out.is_empty() && !commented {
+                    out.push('\n');
+                }
+                out.push_str(&format!(
+                    "- {}:\n    id: {}\n",
+                    type_key(card.expression_type),
+                    card.id
+                ));
+                let lines = card
+                    .lines
+                    .iter()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<&str>>();
+                if lines.is_empty() {
+                    continue;
+                }
+                // fields are bulleted, which reads back as the same fields
+                let bullet = match lines.iter().all(|line| line.contains(':')) {
+                    true => "+ ",
+                    false => "",
+                };
+                out.push_str("    text: |\n");
+                for line in lines {
+                    out.push_str(&format!("      {}{}\n", bullet, line));
+                }
+            }
+        }
+    }
+}
+
+// the model as emlyml yaml. emlyml has no contexts, chains or shared bodies,
+// so cards carry their qualified ids and resolved text, and chains are split
+// into single arrows
+pub fn to_emlyml(model: &EventModel) -> Result<String, String> {
+    let flat = model.flatten()?;
+    let mut out = String::new();
+    write(&model.expressions, &flat, &mut (0, 0), &mut out);
+    Ok(format!("---\n{}", out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_from_emlyml() {
+        let input = indoc! {"
+            # a todo list
+            ---
+            - Form:
+                id: TodoForm
+                text: |
+                  + Title: milk
+                  + Done: no
+            - command: {id: AddTodo}
+            - View:
+                id: Todos
+                text: |
+                  | Title | Done |
+                  | milk  | no   |
+            - =>: {begin_at: TodoForm, end_at: AddTodo}
+            - arrow: {begin_at: AddTodo, end_at: Todos}
+        "};
+        let expected = EventModel {
+            expressions: vec![
+                Expression::Form(
+                    ExpressionId("TodoForm".to_string()),
+                    Body::FieldBody(vec![
+                        Field::Text(TextField {
+                            name: "Title".to_string(),
+                            data: "milk".to_string(),
+                        }),
+                        Field::Text(TextField {
+                            name: "Done".to_string(),
+                            data: "no".to_string(),
+                        }),
+                    ]),
+                ),
+                Expression::Command(ExpressionId("AddTodo".to_string()), Body::FieldBody(vec![])),
+                Expression::View(
+                    ExpressionId("Todos".to_string()),
+                    Body::TableBody(vec![
+                        "| Title | Done |".to_string(),
+                        "| milk  | no   |".to_string(),
+                    ]),
+                ),
+                Expression::Flow(
+                    ExpressionId("flow-TodoForm-AddTodo".to_string()),
+                    vec![
+                        ExpressionId("TodoForm".to_string()),
+                        ExpressionId("AddTodo".to_string()),
+                    ],
+                ),
+                Expression::Flow(
+                    ExpressionId("flow-AddTodo-Todos".to_string()),
+                    vec![
+                        ExpressionId("AddTodo".to_string()),
+                        ExpressionId("Todos".to_string()),
+                    ],
+                ),
+            ],
+        };
+        let observed = from_emlyml(input).unwrap();
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_from_emlyml_errors() {
+        let unknown = "- Form: {id: A}\n- =>: {begin_at: A, end_at: B}\n";
+        assert_eq!(
+            from_emlyml(unknown).unwrap_err(),
+            "emlyml model: entry 2: unknown card B"
+        );
+        let saga = "- Saga: {id: A}\n";
+        assert!(from_emlyml(saga)
+            .unwrap_err()
+            .contains("unknown entry saga"));
+        let two = "- {Form: {id: A}, Command: {id: B}}\n";
+        assert!(from_emlyml(two).is_err());
+    }
+
+    #[test]
+    fn test_to_emlyml() {
+        let model = crate::parse::parse(indoc! {"
+            # eml: 0.0.1
+            form TodoForm { Title: milk }
+            context Todos {
+              command AddTodo { use TodoForm }
+              view Todos {
+                | Title |
+                | milk  |
+              }
+            }
+            flow { TodoForm => Todos.AddTodo => Todos.Todos }
+        "})
+        .unwrap();
+        let expected = indoc! {"
+            ---
+            - Form:
+                id: TodoForm
+                text: |
+                  + Title: milk
+
+            - Command:
+                id: Todos.AddTodo
+                text: |
+                  + Title: milk
+
+            - View:
+                id: Todos.Todos
+                text: |
+                  | Title |
+                  | milk  |
+            - =>: {begin_at: TodoForm, end_at: Todos.AddTodo}
+            - =>: {begin_at: Todos.AddTodo, end_at: Todos.Todos}
+        "};
+        let observed = to_emlyml(&model).unwrap();
+        assert_eq!(expected, observed);
+        let lines = match &from_emlyml(&observed).unwrap().expressions[0] {
+            Expression::Form(_, Body::FieldBody(fields)) => fields.len(),
+            _ => 0,
+        };
+        assert_eq!(lines, 1);
+        // eml allows a flow with no steps, which has no arrows to write
+        let empty = crate::parse::parse("# eml: 0.0.1\nform A { }\nflow { }\n").unwrap();
+        assert_eq!(to_emlyml(&empty).unwrap(), "---\n- Form:\n    id: A\n");
+    }
+
+    #[test]
+    fn test_is_emlyml() {
+        assert!(is_emlyml("# Sample\n\n---\n- Form: {id: A}"));
+        assert!(is_emlyml("- Form: {id: A}"));
+        assert!(!is_emlyml("# eml: 0.0.1\nform A {}"));
+        assert!(!is_emlyml("{\"version\": 1}"));
+    }
+}
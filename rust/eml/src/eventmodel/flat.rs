@@ -0,0 +1,176 @@
+use super::*;
+
+// a card of the model with its context resolved into the id
+#[derive(Debug, PartialEq)]
+pub struct FlatCard {
+    pub id: String,
+    pub expression_type: ExpressionType,
+    pub lines: Vec<String>,
+    pub context: Option<String>,
+}
+
+// the model as cards and the flows between them, for exporters that do not
+// need the svg layout
+#[derive(Debug, Default, PartialEq)]
+pub struct FlatModel {
+    pub cards: Vec<FlatCard>,
+    pub flows: Vec<(String, String)>,
+}
+
+impl FlatModel {
+    fn resolve(&self, context: Option<&ExpressionId>, id: &ExpressionId) -> Result<String, String> {
+        let resolved = id.resolve(context, |id| self.cards.iter().any(|c| c.id == id))?;
+        Ok(resolved.0)
+    }
+
+    fn add(
+        &mut self,
+        context: Option<&ExpressionId>,
+        expressions: &[Expression],
+    ) -> Result<(), String> {
+        for expr in expressions {
+            let (expression_type, id, body) = match expr {
+                Expression::Form(id, body) => (ExpressionType::Form, id, body),
+                Expression::Job(id, body) => (ExpressionType::Job, id, body),
+                Expression::Command(id, body) => (ExpressionType::Command, id, body),
+                Expression::Event(id, body) => (ExpressionType::Event, id, body),
+                Expression::View(id, body) => (ExpressionType::View, id, body),
+                Expression::Flow(_, ids) => {
+                    let ids = ids
+                        .iter()
+                        .map(|id| self.resolve(context, id))
+                        .collect::<Result<Vec<String>, String>>()?;
+                    for pair in ids.windows(2) {
+                        self.flows.push((pair[0].clone(), pair[1].clone()));
+                    }
+                    continue;
+                }
+                Expression::Comment(_) => continue,
+                Expression::Context(id, exprs) => {
+                    let id = match context {
+                        Some(ctx) => id.qualified(ctx),
+                        None => ExpressionId(id.0.clone()),
+                    };
+                    self.add(Some(&id), exprs)?;
+                    continue;
+                }
+            };
+            let lines = match body {
+                Body::FieldBody(fields) => fields
+                    .iter()
+                    .map(|f| match f {
+                        Field::Text(ff) => format!("{}: {}", ff.name, ff.data),
+                    })
+                    .collect::<Vec<String>>(),
+                Body::TableBody(tbl) => tbl.clone(),
+                Body::UseBody(other) => {
+                    let other = self.resolve(context, other)?;
+                    self.cards
+                        .iter()
+                        .find(|c| c.id == other)
+                        .map_or(Vec::new(), |c| c.lines.clone())
+                }
+            };
+            self.cards.push(FlatCard {
+                id: match context {
+                    Some(ctx) => id.qualified(ctx).0,
+                    None => id.0.clone(),
+                },
+                expression_type,
+                lines,
+                context: context.map(|ctx| ctx.0.clone()),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl EventModel {
+    // the cards and flows with every reference resolved, or the first one
+    // that does not find a card declared above it
+    pub fn flatten(&self) -> Result<FlatModel, String> {
+        let mut flat = FlatModel::default();
+        flat.add(None, &self.expressions)?;
+        Ok(flat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flatten() {
+        let model = EventModel {
+            expressions: vec![
+                Expression::Command(
+                    ExpressionId("AddAccount".to_string()),
+                    Body::FieldBody(vec![Field::Text(TextField {
+                        name: "Name".to_string(),
+                        data: "John".to_string(),
+                    })]),
+                ),
+                Expression::Context(
+                    ExpressionId("Billing".to_string()),
+                    vec![
+                        Expression::Event(
+                            ExpressionId("AccountAdded".to_string()),
+                            Body::UseBody(ExpressionId("AddAccount".to_string())),
+                        ),
+                        Expression::Flow(
+                            ExpressionId("flow-AddAccount-AccountAdded".to_string()),
+                            vec![
+                                ExpressionId("AddAccount".to_string()),
+                                ExpressionId("AccountAdded".to_string()),
+                            ],
+                        ),
+                    ],
+                ),
+            ],
+        };
+        let expected = FlatModel {
+            cards: vec![
+                FlatCard {
+                    id: "AddAccount".to_string(),
+                    expression_type: ExpressionType::Command,
+                    lines: vec!["Name: John".to_string()],
+                    context: None,
+                },
+                FlatCard {
+                    id: "Billing.AccountAdded".to_string(),
+                    expression_type: ExpressionType::Event,
+                    lines: vec!["Name: John".to_string()],
+                    context: Some("Billing".to_string()),
+                },
+            ],
+            flows: vec![("AddAccount".to_string(), "Billing.AccountAdded".to_string())],
+        };
+        let observed = model.flatten().unwrap();
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_flatten_dangling() {
+        let card = |id: &str, body: Body| Expression::Command(ExpressionId(id.to_string()), body);
+        let flow = |ids: &[&str]| {
+            Expression::Flow(
+                ExpressionId("flow".to_string()),
+                ids.iter().map(|id| ExpressionId(id.to_string())).collect(),
+            )
+        };
+        let dangling = [
+            vec![card("A", Body::UseBody(ExpressionId("Nope".to_string())))],
+            vec![card("A", Body::FieldBody(vec![])), flow(&["A", "Nope"])],
+            // cards are declared before they are used
+            vec![
+                card("A", Body::FieldBody(vec![])),
+                flow(&["A", "Nope"]),
+                card("Nope", Body::FieldBody(vec![])),
+            ],
+        ];
+        for expressions in dangling {
+            let observed = EventModel { expressions }.flatten().unwrap_err();
+            assert_eq!(observed, "unknown id Nope");
+        }
+    }
+}
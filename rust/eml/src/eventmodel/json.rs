@@ -0,0 +1,329 @@
+use super::*;
+use crate::print::{check_id, check_reference};
+use crate::utils::flow_id;
+
+// bumped whenever the json shape of a model changes incompatibly
+pub const VERSION: u32 = 1;
+
+// the top level of a json model: { "version": 1, "expressions": [...] }
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Document {
+    version: u32,
+    expressions: Vec<Expression>,
+}
+
+impl From<EventModel> for Document {
+    fn from(model: EventModel) -> Self {
+        Document {
+            version: VERSION,
+            expressions: model.expressions,
+        }
+    }
+}
+
+impl TryFrom<Document> for EventModel {
+    type Error = String;
+
+    fn try_from(document: Document) -> Result<Self, Self::Error> {
+        if document.version != VERSION {
+            return Err(format!(
+                "unsupported json model version {}, expected {}",
+                document.version, VERSION
+            ));
+        }
+        check(&document.expressions, false)?;
+        let model = EventModel {
+            expressions: document.expressions,
+        };
+        model.flatten()?;
+        Ok(model)
+    }
+}
+
+// json skips the grammar, so ids and flows are held to what eml can say
+fn check(expressions: &[Expression], in_context: bool) -> Result<(), String> {
+    for expr in expressions {
+        match expr {
+            Expression::Form(id, body)
+            | Expression::Job(id, body)
+            | Expression::Command(id, body)
+            | Expression::Event(id, body)
+            | Expression::View(id, body) => {
+                check_id(id)?;
+                if let Body::UseBody(id) = body {
+                    check_reference(id)?;
+                }
+            }
+            Expression::Flow(_, steps) => {
+                if steps.is_empty() {
+                    return Err("a flow needs at least one step".to_string());
+                }
+                for step in steps {
+                    check_reference(step)?;
+                }
+            }
+            Expression::Context(id, exprs) => {
+                if in_context {
+                    return Err("contexts cannot be nested".to_string());
+                }
+                check_id(id)?;
+                check(exprs, true)?;
+            }
+            Expression::Comment(_) => {}
+        }
+    }
+    Ok(())
+}
+
+// an expression as a json object tagged by its type. flow ids are derived
+// from their steps, so flows carry only the steps
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Node {
+    Form {
+        id: ExpressionId,
+        body: Body,
+    },
+    Job {
+        id: ExpressionId,
+        body: Body,
+    },
+    Command {
+        id: ExpressionId,
+        body: Body,
+    },
+    Event {
+        id: ExpressionId,
+        body: Body,
+    },
+    View {
+        id: ExpressionId,
+        body: Body,
+    },
+    Flow {
+        steps: Vec<ExpressionId>,
+    },
+    Context {
+        id: ExpressionId,
+        expressions: Vec<Expression>,
+    },
+    Comment {
+        text: String,
+    },
+}
+
+impl From<Expression> for Node {
+    fn from(expression: Expression) -> Self {
+        match expression {
+            Expression::Form(id, body) => Node::Form { id, body },
+            Expression::Job(id, body) => Node::Job { id, body },
+            Expression::Command(id, body) => Node::Command { id, body },
+            Expression::Event(id, body) => Node::Event { id, body },
+            Expression::View(id, body) => Node::View { id, body },
+            Expression::Flow(_, steps) => Node::Flow { steps },
+            Expression::Context(id, expressions) => Node::Context { id, expressions },
+            Expression::Comment(text) => Node::Comment { text },
+        }
+    }
+}
+
+impl From<Node> for Expression {
+    fn from(node: Node) -> Self {
+        match node {
+            Node::Form { id, body } => Expression::Form(id, body),
+            Node::Job { id, body } => Expression::Job(id, body),
+            Node::Command { id, body } => Expression::Command(id, body),
+            Node::Event { id, body } => Expression::Event(id, body),
+            Node::View { id, body } => Expression::View(id, body),
+            Node::Flow { steps } => {
+                let id = ExpressionId(flow_id(steps.iter().map(|s| s.0.as_str())));
+                Expression::Flow(id, steps)
+            }
+            Node::Context { id, expressions } => Expression::Context(id, expressions),
+            Node::Comment { text } => Expression::Comment(text),
+        }
+    }
+}
+
+// json input starts with an object, eml input with its header comment
+pub fn is_json(input: &str) -> bool {
+    input.trim_start().starts_with('{')
+}
+
+pub fn from_json(input: &str) -> Result<EventModel, String> {
+    serde_json::from_str(input).map_err(|e| format!("json model: {}", e))
+}
+
+pub fn to_json(model: &EventModel) -> String {
+    serde_json::to_string_pretty(model).unwrap() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_to_json() {
+        let model = parse(indoc! {"
+            # eml: 0.0.1
+            form AddTodoForm { title: milk }
+            command AddTodo { use AddTodoForm }
+            context Todos {
+              view Todos {
+                | title |
+                | milk  |
+              }
+            }
+            flow { AddTodoForm => AddTodo }
+        "})
+        .unwrap();
+        let expected = indoc! {r#"
+            {
+              "version": 1,
+              "expressions": [
+                {
+                  "type": "form",
+                  "id": "AddTodoForm",
+                  "body": {
+                    "fields": [
+                      {
+                        "type": "text",
+                        "name": "title",
+                        "data": "milk "
+                      }
+                    ]
+                  }
+                },
+                {
+                  "type": "command",
+                  "id": "AddTodo",
+                  "body": {
+                    "use": "AddTodoForm"
+                  }
+                },
+                {
+                  "type": "context",
+                  "id": "Todos",
+                  "expressions": [
+                    {
+                      "type": "view",
+                      "id": "Todos",
+                      "body": {
+                        "table": [
+                          "",
+                          "| title |",
+                          "| milk  |",
+                          ""
+                        ]
+                      }
+                    }
+                  ]
+                },
+                {
+                  "type": "flow",
+                  "steps": [
+                    "AddTodoForm",
+                    "AddTodo"
+                  ]
+                }
+              ]
+            }
+        "#};
+        let observed = to_json(&model);
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let model = parse(indoc! {"
+            # eml: 0.0.1
+            form AddTodoForm { title: milk, done: no }
+            command AddTodo { use AddTodoForm }
+            event TodoAdded { use AddTodo }
+            context Todos {
+              view Todos {
+                | title |
+                | milk  |
+              }
+              flow { TodoAdded => Todos }
+            }
+            flow { AddTodoForm => AddTodo => TodoAdded }
+        "})
+        .unwrap();
+        let observed = from_json(&to_json(&model)).unwrap();
+        assert_eq!(model, observed);
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        let future = r#"{ "version": 2, "expressions": [] }"#;
+        assert!(from_json(future).unwrap_err().contains("version 2"));
+        let unknown = r#"{ "version": 1, "expressions": [{ "type": "saga", "id": "X" }] }"#;
+        assert!(from_json(unknown).is_err());
+    }
+
+    fn rejected(expressions: &str) -> String {
+        from_json(&format!(
+            r#"{{ "version": 1, "expressions": [{}] }}"#,
+            expressions
+        ))
+        .unwrap_err()
+    }
+
+    const FORM: &str = r#"{ "type": "form", "id": "A", "body": { "fields": [] } }"#;
+
+    #[test]
+    fn test_from_json_invalid_id() {
+        let card = r#"{ "type": "form", "id": "a b<", "body": { "fields": [] } }"#;
+        assert!(rejected(card).contains("a b< is not a valid eml id"));
+        let context = r#"{ "type": "context", "id": "A.B", "expressions": [] }"#;
+        assert!(rejected(context).contains("A.B is not a valid eml id"));
+    }
+
+    #[test]
+    fn test_from_json_invalid_reference() {
+        let card = r#"{ "type": "command", "id": "B", "body": { "use": "A." } }"#;
+        assert!(
+            rejected(&format!("{}, {}", FORM, card)).contains("A. is not a valid eml reference")
+        );
+        let flow = r#"{ "type": "flow", "steps": ["A", "a-b"] }"#;
+        assert!(
+            rejected(&format!("{}, {}", FORM, flow)).contains("a-b is not a valid eml reference")
+        );
+    }
+
+    #[test]
+    fn test_from_json_empty_flow() {
+        let flow = r#"{ "type": "flow", "steps": [] }"#;
+        assert!(rejected(flow).contains("a flow needs at least one step"));
+    }
+
+    #[test]
+    fn test_from_json_unknown_use() {
+        let card = r#"{ "type": "command", "id": "B", "body": { "use": "Nope" } }"#;
+        assert!(rejected(card).contains("unknown id Nope"));
+    }
+
+    #[test]
+    fn test_from_json_unknown_step() {
+        let flow = r#"{ "type": "flow", "steps": ["A", "Nope"] }"#;
+        assert!(rejected(&format!("{}, {}", FORM, flow)).contains("unknown id Nope"));
+    }
+
+    #[test]
+    fn test_from_json_nested_context() {
+        let nested = r#"{ "type": "context", "id": "A", "expressions": [
+            { "type": "context", "id": "B", "expressions": [] }
+        ] }"#;
+        assert!(rejected(nested).contains("contexts cannot be nested"));
+    }
+
+    #[test]
+    fn test_is_json() {
+        assert!(is_json("\n  { \"version\": 1 }"));
+        assert!(!is_json("# eml: 0.0.1\nform A {}"));
+    }
+}
@@ -1,21 +1,61 @@
+pub mod emlyml;
+pub mod flat;
+pub mod json;
+
+pub use flat::FlatModel;
+use serde::{Deserialize, Serialize};
+
 // A Field is a single key: value pair (maybe Record is better?)
 // A Series is a key: [values] pair
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct TextField {
     pub name: String,
     pub data: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Field {
     Text(TextField),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct ExpressionId(pub String);
 
-#[derive(Debug, PartialEq)]
+impl ExpressionId {
+    // ids inside a context are written bare and qualified as Context.Id
+    pub fn is_qualified(&self) -> bool {
+        self.0.contains('.')
+    }
+
+    pub fn qualified(&self, context: &ExpressionId) -> ExpressionId {
+        ExpressionId(format!("{}.{}", context.0, self.0))
+    }
+
+    // the card a reference finds among those declared so far. a bare id
+    // inside a context prefers that context's own card
+    pub fn resolve(
+        &self,
+        context: Option<&ExpressionId>,
+        declared: impl Fn(&str) -> bool,
+    ) -> Result<ExpressionId, String> {
+        let resolved = match context {
+            Some(ctx) if !self.is_qualified() && declared(&self.qualified(ctx).0) => {
+                self.qualified(ctx)
+            }
+            _ => self.clone(),
+        };
+        match declared(&resolved.0) {
+            true => Ok(resolved),
+            false => Err(format!("unknown id {}", self.0)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ExpressionType {
     Form,
     Job,
@@ -23,16 +63,22 @@ pub enum ExpressionType {
     Event,
     View,
     Flow,
+    Context,
 }
 
-#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Body {
+    #[serde(rename = "fields")]
     FieldBody(Vec<Field>),
+    #[serde(rename = "table")]
     TableBody(Vec<String>),
+    #[serde(rename = "use")]
     UseBody(ExpressionId),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "json::Node", into = "json::Node")]
 pub enum Expression {
     Form(ExpressionId, Body),
     Job(ExpressionId, Body),
@@ -40,9 +86,13 @@ pub enum Expression {
     Event(ExpressionId, Body),
     View(ExpressionId, Body),
     Flow(ExpressionId, Vec<ExpressionId>),
+    Context(ExpressionId, Vec<Expression>),
+    // the text after the #, kept so the model can be written back out
+    Comment(String),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "json::Document", into = "json::Document")]
 pub struct EventModel {
     pub expressions: Vec<Expression>,
 }
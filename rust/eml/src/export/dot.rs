@@ -0,0 +1,115 @@
+use super::{card_style, lane, LANES};
+use crate::eventmodel::FlatModel;
+use crate::svg::Theme;
+
+// a double quoted dot string
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// left justified lines, dot's \l also ends the last line
+fn label(title: &str, lines: &[String]) -> String {
+    let text = std::iter::once(title)
+        .chain(lines.iter().map(|l| l.as_str()))
+        .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+        .collect::<Vec<String>>()
+        .join("\\l");
+    format!("\"{}\\l\"", text)
+}
+
+// a graphviz digraph with one cluster per lane
+pub fn render(model: &FlatModel, theme: &Theme, title: &str) -> String {
+    let mut out = vec![
+        format!("digraph {} {{", quote(title)),
+        "  rankdir=LR;".to_string(),
+        format!(
+            "  node [shape=box, style=filled, fontname={}, fontsize={}];",
+            quote(&theme.font_family),
+            theme.font_size
+        ),
+        format!(
+            "  edge [color={}, penwidth={}];",
+            quote(&theme.arrow.stroke),
+            theme.arrow.stroke_width
+        ),
+    ];
+    for (index, name) in LANES.iter().enumerate() {
+        out.push(format!("  subgraph cluster_lane{} {{", index));
+        out.push(format!("    label={};", quote(name)));
+        out.push(format!("    color={};", quote(&theme.lane.stroke)));
+        for card in model
+            .cards
+            .iter()
+            .filter(|c| lane(&c.expression_type) == Some(index))
+        {
+            let style = card_style(theme, &card.expression_type);
+            out.push(format!(
+                "    {} [label={}, fillcolor={}, color={}, fontcolor={}];",
+                quote(&card.id),
+                label(&card.id, &card.lines),
+                quote(&style.fill),
+                quote(&style.stroke),
+                quote(&style.text),
+            ));
+        }
+        out.push("  }".to_string());
+    }
+    for (from, to) in model.flows.iter() {
+        out.push(format!("  {} -> {};", quote(from), quote(to)));
+    }
+    out.push("}".to_string());
+    out.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventmodel::flat::FlatCard;
+    use crate::eventmodel::ExpressionType;
+    use indoc::indoc;
+
+    #[test]
+    fn test_render_dot() {
+        let model = FlatModel {
+            cards: vec![
+                FlatCard {
+                    id: "AddTodo".to_string(),
+                    expression_type: ExpressionType::Command,
+                    lines: vec!["title: \"milk\"".to_string()],
+                    context: None,
+                },
+                FlatCard {
+                    id: "TodoAdded".to_string(),
+                    expression_type: ExpressionType::Event,
+                    lines: vec![],
+                    context: None,
+                },
+            ],
+            flows: vec![("AddTodo".to_string(), "TodoAdded".to_string())],
+        };
+        let expected = indoc! {r##"
+            digraph "todo" {
+              rankdir=LR;
+              node [shape=box, style=filled, fontname="monospace", fontsize=16];
+              edge [color="black", penwidth=2];
+              subgraph cluster_lane0 {
+                label="Forms & Jobs";
+                color="black";
+              }
+              subgraph cluster_lane1 {
+                label="Commands & Views";
+                color="black";
+                "AddTodo" [label="AddTodo\ltitle: \"milk\"\l", fillcolor="#60b3f7", color="black", fontcolor="black"];
+              }
+              subgraph cluster_lane2 {
+                label="Events";
+                color="black";
+                "TodoAdded" [label="TodoAdded\l", fillcolor="#f7a660", color="black", fontcolor="black"];
+              }
+              "AddTodo" -> "TodoAdded";
+            }
+        "##};
+        let observed = render(&model, &Theme::default(), "todo");
+        assert_eq!(expected, observed);
+    }
+}
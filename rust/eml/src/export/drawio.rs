@@ -0,0 +1,200 @@
+use crate::svg::xml::escape;
+use crate::svg::{Card, Point, SvgDocument};
+
+// card text as the html drawio shows in a cell, spaces kept for tables
+fn html_label(card: &Card) -> String {
+    let html = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace(' ', "&nbsp;")
+    };
+    let mut label = format!("<b>{}</b>", html(&card.id));
+    if !card.text_lines.is_empty() {
+        label.push_str("<hr>");
+        label.push_str(
+            &card
+                .text_lines
+                .iter()
+                .map(|line| html(line))
+                .collect::<Vec<String>>()
+                .join("<br>"),
+        );
+    }
+    label
+}
+
+// where a point sits on a card as fractions of its width and height
+fn constraint(card: &Card, point: &Point) -> (f64, f64) {
+    let x = point.x.clamp(card.origin.x, card.origin.x + card.width);
+    let y = point.y.clamp(card.origin.y, card.origin.y + card.height);
+    (
+        (x - card.origin.x) / card.width,
+        (y - card.origin.y) / card.height,
+    )
+}
+
+fn cell_id(prefix: &str, id: &str) -> String {
+    escape(&format!("{}-{}", prefix, id))
+}
+
+// an mxgraph file with every cell where set_dimensions placed it
+pub fn render(doc: &SvgDocument) -> String {
+    let theme = &doc.theme;
+    let mut cells = vec![
+        "<mxCell id='0' />".to_string(),
+        "<mxCell id='1' parent='0' />".to_string(),
+    ];
+    for context in doc.contexts.iter().filter(|c| !c.card_ids.is_empty()) {
+        cells.push(format!(
+            "<mxCell id='{id}' value='{value}' style='rounded=0;whiteSpace=wrap;html=1;fillColor=none;dashed=1;strokeColor={stroke};strokeWidth={width};fontColor={stroke};fontFamily={font};fontStyle=1;align=left;verticalAlign=top;spacingLeft=8;' vertex='1' parent='1'><mxGeometry x='{x}' y='{y}' width='{w}' height='{h}' as='geometry' /></mxCell>",
+            id = cell_id("context", &context.id),
+            value = escape(&context.id),
+            stroke = escape(&theme.context.stroke),
+            width = theme.context.stroke_width,
+            font = escape(&theme.font_family),
+            x = context.origin.x,
+            y = context.origin.y,
+            w = context.width,
+            h = context.height,
+        ));
+    }
+    for (name, y) in [("top", doc.swimlane.top), ("bottom", doc.swimlane.bottom)] {
+        cells.push(format!(
+            "<mxCell id='lane-{name}' style='endArrow=none;html=1;strokeColor={stroke};strokeWidth={width};' edge='1' parent='1'><mxGeometry relative='1' as='geometry'><mxPoint x='0' y='{y}' as='sourcePoint' /><mxPoint x='{right}' y='{y}' as='targetPoint' /></mxGeometry></mxCell>",
+            name = name,
+            stroke = escape(&theme.lane.stroke),
+            width = theme.lane.stroke_width,
+            y = y,
+            right = doc.width,
+        ));
+    }
+    for card in doc.cards.iter() {
+        let style = theme.card_style(&card.card_type);
+        cells.push(format!(
+            "<mxCell id='{id}' value='{value}' style='rounded=0;whiteSpace=wrap;html=1;fillColor={fill};strokeColor={stroke};strokeWidth={width};fontColor={text};fontFamily={font};fontSize={size};align=left;verticalAlign=top;spacing={spacing};' vertex='1' parent='1'><mxGeometry x='{x}' y='{y}' width='{w}' height='{h}' as='geometry' /></mxCell>",
+            id = cell_id("card", &card.id),
+            value = escape(&html_label(card)),
+            fill = escape(&style.fill),
+            stroke = escape(&style.stroke),
+            width = theme.card_stroke_width,
+            text = escape(&style.text),
+            font = escape(&theme.font_family),
+            size = theme.font_size,
+            spacing = card.text_translate.x - card.origin.x,
+            x = card.origin.x,
+            y = card.origin.y,
+            w = card.width,
+            h = card.height,
+        ));
+    }
+    for arrow in doc.arrows.iter() {
+        let (first, last) = match (arrow.points.first(), arrow.points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => continue,
+        };
+        let (exit_x, exit_y) = constraint(&arrow.begin_at, first);
+        let (entry_x, entry_y) = constraint(&arrow.end_at, last);
+        // drawio draws the ends itself, so only the bends are kept
+        let waypoints = arrow
+            .points
+            .iter()
+            .skip(1)
+            .take(arrow.points.len().saturating_sub(2))
+            .map(|p| format!("<mxPoint x='{}' y='{}' />", p.x, p.y))
+            .collect::<Vec<String>>()
+            .join("");
+        cells.push(format!(
+            "<mxCell id='{id}' style='edgeStyle=orthogonalEdgeStyle;rounded=0;html=1;endArrow=block;endFill=1;strokeColor={stroke};strokeWidth={width};exitX={exit_x};exitY={exit_y};exitDx=0;exitDy=0;entryX={entry_x};entryY={entry_y};entryDx=0;entryDy=0;' edge='1' parent='1' source='{source}' target='{target}'><mxGeometry relative='1' as='geometry'><Array as='points'>{waypoints}</Array></mxGeometry></mxCell>",
+            id = cell_id("arrow", &arrow.id),
+            stroke = escape(&theme.arrow.stroke),
+            width = theme.arrow.stroke_width,
+            exit_x = exit_x,
+            exit_y = exit_y,
+            entry_x = entry_x,
+            entry_y = entry_y,
+            source = cell_id("card", &arrow.begin_at.id),
+            target = cell_id("card", &arrow.end_at.id),
+            waypoints = waypoints,
+        ));
+    }
+    format!(
+        "<mxfile host='eml'><diagram id='{id}' name='{name}'><mxGraphModel grid='1' gridSize='10' guides='1' tooltips='1' connect='1' arrows='1' fold='1' page='0' pageScale='1' pageWidth='{width}' pageHeight='{height}' math='0' shadow='0'{background}><root>{cells}</root></mxGraphModel></diagram></mxfile>\n",
+        id = escape(&doc.id),
+        name = escape(&doc.title),
+        width = doc.width,
+        height = doc.height,
+        background = theme
+            .background
+            .as_ref()
+            .map(|color| format!(" background='{}'", escape(color)))
+            .unwrap_or_default(),
+        cells = cells.join(""),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::{Arrow, CardType, ContextFrame, SvgConfig, Theme};
+
+    fn document() -> SvgDocument {
+        let mut doc = SvgDocument::new(Theme::default());
+        doc.cards.push(Card::new(
+            "AddTodo".to_string(),
+            CardType::Command,
+            vec!["title: <milk> & 'eggs'".to_string()],
+        ));
+        doc.cards
+            .push(Card::new("TodoAdded".to_string(), CardType::Event, vec![]));
+        doc.arrows
+            .push(Arrow::new(doc.cards[0].clone(), doc.cards[1].clone()));
+        doc.contexts.push(ContextFrame::new(
+            "Todos".to_string(),
+            vec!["AddTodo".to_string(), "TodoAdded".to_string()],
+        ));
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_render_drawio() {
+        let doc = document();
+        let observed = render(&doc);
+        let xml = roxmltree::Document::parse(&observed).unwrap();
+        let cell = |id: &str| {
+            xml.descendants()
+                .find(|n| n.has_tag_name("mxCell") && n.attribute("id") == Some(id))
+                .unwrap()
+        };
+        let card = cell("card-AddTodo");
+        assert_eq!(
+            card.attribute("value"),
+            Some("<b>AddTodo</b><hr>title:&nbsp;&lt;milk&gt;&nbsp;&amp;&nbsp;'eggs'")
+        );
+        let geometry = card.first_element_child().unwrap();
+        assert_eq!(geometry.attribute("x"), Some("150"));
+        assert_eq!(geometry.attribute("width"), Some("300"));
+        let arrow = cell("arrow-flow-AddTodo-TodoAdded");
+        assert_eq!(arrow.attribute("source"), Some("card-AddTodo"));
+        assert_eq!(arrow.attribute("target"), Some("card-TodoAdded"));
+        assert!(arrow
+            .attribute("style")
+            .unwrap()
+            .contains("exitX=0.5;exitY=1;"));
+        assert!(arrow
+            .attribute("style")
+            .unwrap()
+            .contains("entryX=0.5;entryY=0;"));
+        assert!(cell("context-Todos")
+            .attribute("style")
+            .unwrap()
+            .contains("dashed=1"));
+        assert_eq!(
+            xml.descendants()
+                .filter(|n| n.has_tag_name("mxCell"))
+                .count(),
+            8
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use crate::svg::{Card, Point, SvgDocument};
+use crate::utils::content_hash;
+use serde_json::{json, Value};
+
+// excalidraw's monospace font
+const CASCADIA: u32 = 3;
+
+// seeds only vary the hand drawn strokes, deriving them keeps output stable
+fn seed(id: &str) -> u64 {
+    u64::from_str_radix(&content_hash(id)[..8], 16).unwrap()
+}
+
+// the properties every excalidraw element carries
+fn element(kind: &str, id: &str, x: f64, y: f64, width: f64, height: f64) -> Value {
+    json!({
+        "type": kind,
+        "id": id,
+        "x": x,
+        "y": y,
+        "width": width,
+        "height": height,
+        "angle": 0,
+        "strokeColor": "#1e1e1e",
+        "backgroundColor": "transparent",
+        "fillStyle": "solid",
+        "strokeWidth": 2,
+        "strokeStyle": "solid",
+        "roughness": 1,
+        "opacity": 100,
+        "groupIds": [],
+        "frameId": null,
+        "roundness": null,
+        "seed": seed(id),
+        "version": 1,
+        "versionNonce": seed(id),
+        "isDeleted": false,
+        "boundElements": [],
+        "updated": 1,
+        "link": null,
+        "locked": false,
+    })
+}
+
+fn card_id(card: &Card) -> String {
+    format!("card-{}", card.id)
+}
+
+fn arrow_id(id: &str) -> String {
+    format!("arrow-{}", id)
+}
+
+fn line(id: &str, from: &Point, to: &Point, stroke: &str, width: f64) -> Value {
+    let mut line = element(
+        "line",
+        id,
+        from.x,
+        from.y,
+        (to.x - from.x).abs(),
+        (to.y - from.y).abs(),
+    );
+    line["strokeColor"] = json!(stroke);
+    line["strokeWidth"] = json!(width);
+    line["points"] = json!([[0, 0], [to.x - from.x, to.y - from.y]]);
+    line["lastCommittedPoint"] = Value::Null;
+    line["startBinding"] = Value::Null;
+    line["endBinding"] = Value::Null;
+    line["startArrowhead"] = Value::Null;
+    line["endArrowhead"] = Value::Null;
+    line
+}
+
+// a scene with sticky notes for cards and arrows bound to them
+pub fn render(doc: &SvgDocument) -> String {
+    let theme = &doc.theme;
+    let mut elements = Vec::new();
+    for (name, y) in [("top", doc.swimlane.top), ("bottom", doc.swimlane.bottom)] {
+        elements.push(line(
+            &format!("lane-{}", name),
+            &Point { x: 0f64, y },
+            &Point { x: doc.width, y },
+            &theme.lane.stroke,
+            theme.lane.stroke_width,
+        ));
+    }
+    for context in doc.contexts.iter().filter(|c| !c.card_ids.is_empty()) {
+        let id = format!("context-{}", context.id);
+        let mut frame = element(
+            "rectangle",
+            &id,
+            context.origin.x,
+            context.origin.y,
+            context.width,
+            context.height,
+        );
+        frame["strokeColor"] = json!(theme.context.stroke);
+        frame["strokeStyle"] = json!("dashed");
+        elements.push(frame);
+        let mut label = element(
+            "text",
+            &format!("{}-label", id),
+            context.origin.x + theme.font_size / 2f64,
+            context.origin.y + theme.font_size / 2f64,
+            context.id.chars().count() as f64 * theme.char_width(),
+            theme.line_height() * 1.25,
+        );
+        label["text"] = json!(context.id);
+        label["originalText"] = json!(context.id);
+        label["fontSize"] = json!(theme.font_size);
+        label["fontFamily"] = json!(CASCADIA);
+        label["textAlign"] = json!("left");
+        label["verticalAlign"] = json!("top");
+        label["containerId"] = Value::Null;
+        label["lineHeight"] = json!(1.25);
+        label["strokeColor"] = json!(theme.context.stroke);
+        elements.push(label);
+    }
+    for card in doc.cards.iter() {
+        let style = theme.card_style(&card.card_type);
+        let id = card_id(card);
+        let text_id = format!("{}-text", id);
+        let mut note = element(
+            "rectangle",
+            &id,
+            card.origin.x,
+            card.origin.y,
+            card.width,
+            card.height,
+        );
+        note["strokeColor"] = json!(style.stroke);
+        note["backgroundColor"] = json!(style.fill);
+        note["strokeWidth"] = json!(theme.card_stroke_width);
+        note["roundness"] = json!({ "type": 3 });
+        let mut bound = vec![json!({ "type": "text", "id": text_id })];
+        for arrow in doc
+            .arrows
+            .iter()
+            .filter(|a| a.begin_at.id == card.id || a.end_at.id == card.id)
+        {
+            bound.push(json!({ "type": "arrow", "id": arrow_id(&arrow.id) }));
+        }
+        note["boundElements"] = json!(bound);
+        elements.push(note);
+        let inset = card.text_translate.x - card.origin.x;
+        // the wrapped lines the note was sized for, as the svg card shows them
+        let text = std::iter::once(card.title())
+            .chain(card.display_lines.iter().cloned())
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut label = element(
+            "text",
+            &text_id,
+            card.origin.x + inset,
+            card.origin.y + inset,
+            card.width - inset * 2f64,
+            card.height - inset * 2f64,
+        );
+        label["strokeColor"] = json!(style.text);
+        label["text"] = json!(text);
+        label["originalText"] = json!(text);
+        label["fontSize"] = json!(theme.font_size);
+        label["fontFamily"] = json!(CASCADIA);
+        label["textAlign"] = json!("left");
+        label["verticalAlign"] = json!("top");
+        label["containerId"] = json!(id);
+        label["lineHeight"] = json!(1.25);
+        elements.push(label);
+    }
+    for arrow in doc.arrows.iter() {
+        let first = match arrow.points.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        let xs = arrow.points.iter().map(|p| p.x);
+        let ys = arrow.points.iter().map(|p| p.y);
+        let width = xs.clone().fold(f64::MIN, f64::max) - xs.fold(f64::MAX, f64::min);
+        let height = ys.clone().fold(f64::MIN, f64::max) - ys.fold(f64::MAX, f64::min);
+        let mut connector = line(
+            &arrow_id(&arrow.id),
+            first,
+            first,
+            &theme.arrow.stroke,
+            theme.arrow.stroke_width,
+        );
+        connector["type"] = json!("arrow");
+        connector["width"] = json!(width);
+        connector["height"] = json!(height);
+        connector["points"] = json!(arrow
+            .points
+            .iter()
+            .map(|p| [p.x - first.x, p.y - first.y])
+            .collect::<Vec<[f64; 2]>>());
+        connector["startBinding"] = json!({
+            "elementId": card_id(&arrow.begin_at),
+            "focus": 0,
+            "gap": 1,
+        });
+        connector["endBinding"] = json!({
+            "elementId": card_id(&arrow.end_at),
+            "focus": 0,
+            "gap": doc.arrowhead,
+        });
+        connector["endArrowhead"] = json!("arrow");
+        elements.push(connector);
+    }
+    let scene = json!({
+        "type": "excalidraw",
+        "version": 2,
+        "source": "eml",
+        "elements": elements,
+        "appState": {
+            "viewBackgroundColor": theme.background.clone().unwrap_or("#ffffff".to_string()),
+            "gridSize": null,
+        },
+        "files": {},
+    });
+    serde_json::to_string_pretty(&scene).unwrap() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::{Arrow, CardType, SvgConfig, Theme};
+
+    #[test]
+    fn test_render_excalidraw() {
+        let mut doc = SvgDocument::new(Theme::default());
+        doc.cards.push(Card::new(
+            "AddTodo".to_string(),
+            CardType::Command,
+            vec!["title: milk".to_string()],
+        ));
+        doc.cards
+            .push(Card::new("TodoAdded".to_string(), CardType::Event, vec![]));
+        doc.arrows
+            .push(Arrow::new(doc.cards[0].clone(), doc.cards[1].clone()));
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        let scene: Value = serde_json::from_str(&render(&doc)).unwrap();
+        let elements = scene["elements"].as_array().unwrap();
+        let find = |id: &str| elements.iter().find(|e| e["id"] == id).unwrap();
+        let note = find("card-AddTodo");
+        assert_eq!(note["backgroundColor"], "#60b3f7");
+        assert_eq!(note["x"], 150f64);
+        let bound = note["boundElements"].as_array().unwrap();
+        assert_eq!(bound.len(), 2);
+        assert_eq!(bound[1]["id"], "arrow-flow-AddTodo-TodoAdded");
+        let text = find("card-AddTodo-text");
+        assert_eq!(text["containerId"], "card-AddTodo");
+        assert_eq!(text["text"], "AddTodo\ntitle: milk");
+        let arrow = find("arrow-flow-AddTodo-TodoAdded");
+        assert_eq!(arrow["type"], "arrow");
+        assert_eq!(arrow["startBinding"]["elementId"], "card-AddTodo");
+        assert_eq!(arrow["endBinding"]["elementId"], "card-TodoAdded");
+        assert_eq!(arrow["points"][0], json!([0f64, 0f64]));
+        // every id is unique
+        let mut ids = elements
+            .iter()
+            .map(|e| e["id"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), elements.len());
+    }
+
+    #[test]
+    fn test_render_excalidraw_wrapped() {
+        let mut doc = SvgDocument::new(Theme::default());
+        doc.cards.push(Card::new(
+            "AddCustomer".to_string(),
+            CardType::Command,
+            vec![format!("Notes: {}", "lorem ipsum ".repeat(20))],
+        ));
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        let scene: Value = serde_json::from_str(&render(&doc)).unwrap();
+        let elements = scene["elements"].as_array().unwrap();
+        let text = elements
+            .iter()
+            .find(|e| e["id"] == "card-AddCustomer-text")
+            .unwrap();
+        let card = &doc.cards[0];
+        let lines = text["text"]
+            .as_str()
+            .unwrap()
+            .lines()
+            .collect::<Vec<&str>>();
+        assert_eq!(lines.len(), card.display_lines.len() + 1);
+        assert!(lines.len() > 2);
+        assert!(lines.iter().all(|l| l.chars().count() <= card.max_chars));
+    }
+}
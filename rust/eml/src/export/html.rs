@@ -0,0 +1,154 @@
+use crate::svg::xml::escape;
+use crate::svg::SvgDocument;
+use serde_json::json;
+
+const TEMPLATE: &str = include_str!("viewer.html");
+
+// substitute {{name}} placeholders in one pass so values are never rescanned
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => {
+                filled.push_str(&rest[..start]);
+                filled.push_str(value);
+            }
+            None => filled.push_str(&rest[..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+// the cards and flows the viewer script works from
+fn model_json(doc: &SvgDocument) -> String {
+    let cards = doc
+        .cards
+        .iter()
+        .map(|card| {
+            json!({
+                "id": card.id,
+                "svg_id": card.xml_id,
+                "card_type": card.card_type.name(),
+                "lines": card.text_lines,
+            })
+        })
+        .collect::<Vec<_>>();
+    let arrows = doc
+        .arrows
+        .iter()
+        .map(|arrow| {
+            json!({
+                "svg_id": arrow.xml_id,
+                "from": arrow.begin_at.id,
+                "to": arrow.end_at.id,
+            })
+        })
+        .collect::<Vec<_>>();
+    // inside a script element, <!-- and </ change how the html tokenizer
+    // reads on, so every character that could start markup is written as a
+    // json escape. line and paragraph separators end a string in older js
+    let mut escaped = String::new();
+    for c in json!({ "cards": cards, "arrows": arrows })
+        .to_string()
+        .chars()
+    {
+        match c {
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// a single page with the diagram, a card list and a details panel
+pub fn render(doc: &SvgDocument) -> String {
+    fill(
+        TEMPLATE,
+        &[
+            ("title", &escape(&doc.title)),
+            ("svg", &doc.render()),
+            ("model", &model_json(doc)),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::{Arrow, Card, CardType, SvgConfig, Theme};
+
+    fn document() -> SvgDocument {
+        let mut doc = SvgDocument::new(Theme::default());
+        doc.title = "</title><script>".to_string();
+        doc.cards.push(Card::new(
+            "AddTodo".to_string(),
+            CardType::Command,
+            vec!["note: </script><script>alert(1)</script>".to_string()],
+        ));
+        doc.cards.push(Card::new(
+            "TodoAdded".to_string(),
+            CardType::Event,
+            vec![
+                "title: {{svg}}".to_string(),
+                "note: <!--<script>".to_string(),
+                "a & b > c \u{2028}".to_string(),
+            ],
+        ));
+        doc.arrows
+            .push(Arrow::new(doc.cards[0].clone(), doc.cards[1].clone()));
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_fill() {
+        let expected = "<p>{{b}} and {{c}}</p>";
+        let observed = fill("<p>{{a}} and {{c}}</p>", &[("a", "{{b}}")]);
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_render_html() {
+        let html = render(&document());
+        assert_eq!(html.matches("<svg xmlns=").count(), 1);
+        assert_eq!(html.matches("</script>").count(), 2);
+        assert!(!html.contains("<!--<script>"));
+        // the viewer script after the model is still its own element
+        let model = html.find("<script id=\"model\"").unwrap();
+        let end = model + html[model..].find("</script>").unwrap();
+        assert!(html[end..].contains("<script>"));
+        assert!(html.contains("<title>&lt;/title&gt;&lt;script&gt;</title>"));
+        // no network dependencies
+        assert!(!html.contains("src=\"http"));
+        assert!(!html.contains("href=\"http"));
+    }
+
+    #[test]
+    fn test_model_json() {
+        let json = model_json(&document());
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["cards"][0]["id"], "AddTodo");
+        assert_eq!(value["cards"][1]["card_type"], "event");
+        assert_eq!(
+            value["cards"][0]["lines"][0],
+            "note: </script><script>alert(1)</script>"
+        );
+        assert_eq!(value["cards"][1]["lines"][1], "note: <!--<script>");
+        assert_eq!(value["cards"][1]["lines"][2], "a & b > c \u{2028}");
+        for markup in ['<', '>', '&', '\u{2028}'] {
+            assert!(!json.contains(markup));
+        }
+        assert_eq!(value["arrows"][0]["svg_id"], "flow-AddTodo-TodoAdded");
+        assert_eq!(value["arrows"][0]["to"], "TodoAdded");
+    }
+}
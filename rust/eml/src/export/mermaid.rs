@@ -0,0 +1,174 @@
+use super::{card_style, card_type, lane, node_ids, LANES};
+use crate::eventmodel::FlatModel;
+use crate::svg::Theme;
+
+// flowchart keywords that cannot stand as a bare node id
+const KEYWORDS: [&str; 10] = [
+    "end",
+    "class",
+    "classDef",
+    "style",
+    "click",
+    "subgraph",
+    "graph",
+    "flowchart",
+    "direction",
+    "linkStyle",
+];
+
+// mermaid ids are plain words that are not keywords, such as the `end` that
+// closes a subgraph
+fn node_id(id: &str) -> String {
+    let id = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if KEYWORDS.iter().any(|k| id.eq_ignore_ascii_case(k)) {
+        format!("{}_", id)
+    } else {
+        id
+    }
+}
+
+// mermaid labels take entity codes in place of characters that end them
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '"' => "#quot;".to_string(),
+            '&' => "#amp;".to_string(),
+            '<' => "#lt;".to_string(),
+            '>' => "#gt;".to_string(),
+            '#' => "#35;".to_string(),
+            _ => c.to_string(),
+        })
+        .collect()
+}
+
+// a flowchart with one subgraph per lane and a class per card type
+pub fn render(model: &FlatModel, theme: &Theme) -> String {
+    let ids = node_ids(model, node_id);
+    let mut out = vec!["flowchart LR".to_string()];
+    for (index, name) in LANES.iter().enumerate() {
+        out.push(format!("  subgraph lane{}[\"{}\"]", index, escape(name)));
+        out.push("    direction LR".to_string());
+        for card in model
+            .cards
+            .iter()
+            .filter(|c| lane(&c.expression_type) == Some(index))
+        {
+            let label = std::iter::once(format!("<b>{}</b>", escape(&card.id)))
+                .chain(card.lines.iter().map(|l| escape(l)))
+                .collect::<Vec<String>>()
+                .join("<br/>");
+            out.push(format!("    {}[\"{}\"]", ids[card.id.as_str()], label));
+        }
+        out.push("  end".to_string());
+    }
+    for (from, to) in model.flows.iter() {
+        out.push(format!("  {} --> {}", ids[from.as_str()], ids[to.as_str()]));
+    }
+    let mut defined: Vec<&str> = Vec::new();
+    for card in model.cards.iter() {
+        let class = match card_type(&card.expression_type) {
+            Some(card_type) => card_type.name(),
+            None => continue,
+        };
+        if !defined.contains(&class) {
+            let style = card_style(theme, &card.expression_type);
+            out.push(format!(
+                "  classDef {} fill:{},stroke:{},color:{},stroke-width:{}px",
+                class, style.fill, style.stroke, style.text, theme.card_stroke_width
+            ));
+            defined.push(class);
+        }
+        out.push(format!("  class {} {}", ids[card.id.as_str()], class));
+    }
+    out.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventmodel::flat::FlatCard;
+    use crate::eventmodel::ExpressionType;
+    use indoc::indoc;
+
+    #[test]
+    fn test_node_id() {
+        assert_eq!(node_id("Billing.AddAccount"), "Billing_AddAccount");
+        assert_eq!(node_id("End"), "End_");
+    }
+
+    #[test]
+    fn test_node_ids_distinct() {
+        let card = |id: &str| FlatCard {
+            id: id.to_string(),
+            expression_type: ExpressionType::Event,
+            lines: vec![],
+            context: None,
+        };
+        let model = FlatModel {
+            cards: vec![
+                card("A.B"),
+                card("A_B"),
+                card("A_B_2"),
+                card("end"),
+                card("Class"),
+                card("linkstyle"),
+                card("Subgraph"),
+            ],
+            flows: vec![("A.B".to_string(), "A_B".to_string())],
+        };
+        let ids = node_ids(&model, node_id);
+        assert_eq!(ids["A.B"], "A_B");
+        assert_eq!(ids["A_B"], "A_B_3");
+        assert_eq!(ids["A_B_2"], "A_B_2");
+        assert_eq!(ids["end"], "end_");
+        assert_eq!(ids["Class"], "Class_");
+        assert_eq!(ids["linkstyle"], "linkstyle_");
+        assert_eq!(ids["Subgraph"], "Subgraph_");
+        assert!(render(&model, &Theme::default()).contains("  A_B --> A_B_3\n"));
+    }
+
+    #[test]
+    fn test_render_mermaid() {
+        let model = FlatModel {
+            cards: vec![
+                FlatCard {
+                    id: "AddTodo".to_string(),
+                    expression_type: ExpressionType::Command,
+                    lines: vec!["title: \"milk\" <2l>".to_string()],
+                    context: None,
+                },
+                FlatCard {
+                    id: "Todos.TodoAdded".to_string(),
+                    expression_type: ExpressionType::Event,
+                    lines: vec![],
+                    context: Some("Todos".to_string()),
+                },
+            ],
+            flows: vec![("AddTodo".to_string(), "Todos.TodoAdded".to_string())],
+        };
+        let expected = indoc! {r##"
+            flowchart LR
+              subgraph lane0["Forms #amp; Jobs"]
+                direction LR
+              end
+              subgraph lane1["Commands #amp; Views"]
+                direction LR
+                AddTodo["<b>AddTodo</b><br/>title: #quot;milk#quot; #lt;2l#gt;"]
+              end
+              subgraph lane2["Events"]
+                direction LR
+                Todos_TodoAdded["<b>Todos.TodoAdded</b>"]
+              end
+              AddTodo --> Todos_TodoAdded
+              classDef command fill:#60b3f7,stroke:black,color:black,stroke-width:2px
+              class AddTodo command
+              classDef event fill:#f7a660,stroke:black,color:black,stroke-width:2px
+              class Todos_TodoAdded event
+        "##};
+        let observed = render(&model, &Theme::default());
+        assert_eq!(expected, observed);
+    }
+}
@@ -0,0 +1,171 @@
+pub mod dot;
+pub mod drawio;
+pub mod excalidraw;
+pub mod html;
+pub mod mermaid;
+pub mod pdf;
+pub mod plantuml;
+pub mod png;
+pub mod text;
+
+use crate::eventmodel::{ExpressionType, FlatModel};
+use crate::svg::{CardStyle, CardType, Theme};
+use resvg::usvg::{fontdb, Options, PostProcessingSteps, Tree, TreeParsing, TreePostProc};
+use std::collections::HashMap;
+use std::path::Path;
+
+// the swimlanes from top to bottom
+pub const LANES: [&str; 3] = ["Forms & Jobs", "Commands & Views", "Events"];
+
+pub fn lane(expression_type: &ExpressionType) -> Option<usize> {
+    match expression_type {
+        ExpressionType::Form | ExpressionType::Job => Some(0),
+        ExpressionType::Command | ExpressionType::View => Some(1),
+        ExpressionType::Event => Some(2),
+        ExpressionType::Flow | ExpressionType::Context => None,
+    }
+}
+
+pub fn card_type(expression_type: &ExpressionType) -> Option<CardType> {
+    match expression_type {
+        ExpressionType::Form => Some(CardType::Form),
+        ExpressionType::Job => Some(CardType::Job),
+        ExpressionType::Command => Some(CardType::Command),
+        ExpressionType::Event => Some(CardType::Event),
+        ExpressionType::View => Some(CardType::View),
+        ExpressionType::Flow | ExpressionType::Context => None,
+    }
+}
+
+// exporters colour cards the same way the svg does
+pub fn card_style<'a>(theme: &'a Theme, expression_type: &ExpressionType) -> &'a CardStyle {
+    match card_type(expression_type) {
+        Some(card_type) => theme.card_style(&card_type),
+        None => &theme.form,
+    }
+}
+
+// node ids for formats that only take words. cards whose ids only differ in
+// characters a word cannot hold are told apart by a number
+pub fn node_ids(model: &FlatModel, word: impl Fn(&str) -> String) -> HashMap<&str, String> {
+    let words = model
+        .cards
+        .iter()
+        .map(|c| word(&c.id))
+        .collect::<Vec<String>>();
+    let mut given: Vec<String> = Vec::with_capacity(words.len());
+    for id in words.iter() {
+        let node_id = match given.contains(id) {
+            true => (2..)
+                .map(|n| format!("{}_{}", id, n))
+                .find(|c| !words.contains(c) && !given.contains(c))
+                .unwrap(),
+            false => id.clone(),
+        };
+        given.push(node_id);
+    }
+    model
+        .cards
+        .iter()
+        .map(|c| c.id.as_str())
+        .zip(given)
+        .collect()
+}
+
+// svg is measured in css pixels, which are 96 to the inch
+pub const CSS_DPI: f32 = 96f32;
+
+fn fonts() -> fontdb::Database {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    // fall back to any installed monospace face when the generic default is missing
+    let monospace = fontdb::Family::Monospace;
+    let has_monospace = fonts.faces().any(|face| {
+        face.families
+            .iter()
+            .any(|(name, _)| *name == fonts.family_name(&monospace))
+    });
+    if !has_monospace {
+        let family = fonts
+            .faces()
+            .find(|face| face.monospaced)
+            .and_then(|face| face.families.first())
+            .map(|(name, _)| name.clone());
+        if let Some(family) = family {
+            fonts.set_monospace_family(family);
+        }
+    }
+    fonts
+}
+
+// parse svg for the vector and raster backends, which cannot draw text as is
+fn parse_svg(svg: &str) -> Result<Tree, String> {
+    let mut tree = Tree::from_str(svg, &Options::default()).map_err(|e| e.to_string())?;
+    let steps = PostProcessingSteps {
+        convert_text_into_paths: true,
+    };
+    tree.postprocess(steps, &fonts());
+    Ok(tree)
+}
+
+// the output formats eml can write
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Svg,
+    Png,
+    Pdf,
+    Html,
+    Dot,
+    Mermaid,
+    Plantuml,
+    Drawio,
+    Excalidraw,
+    Text,
+    Json,
+}
+
+impl Format {
+    // guess the format from an output file extension
+    pub fn from_path(path: &str) -> Option<Format> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "svg" => Some(Format::Svg),
+            "png" => Some(Format::Png),
+            "pdf" => Some(Format::Pdf),
+            "html" | "htm" => Some(Format::Html),
+            "dot" | "gv" => Some(Format::Dot),
+            "mmd" | "mermaid" => Some(Format::Mermaid),
+            "puml" | "plantuml" | "pu" => Some(Format::Plantuml),
+            "drawio" => Some(Format::Drawio),
+            "excalidraw" => Some(Format::Excalidraw),
+            "txt" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("model.svg"), Some(Format::Svg));
+        assert_eq!(Format::from_path("out/Model.PNG"), Some(Format::Png));
+        assert_eq!(Format::from_path("model.pdf"), Some(Format::Pdf));
+        assert_eq!(Format::from_path("model.html"), Some(Format::Html));
+        assert_eq!(Format::from_path("model.gv"), Some(Format::Dot));
+        assert_eq!(Format::from_path("model.mmd"), Some(Format::Mermaid));
+        assert_eq!(Format::from_path("model.puml"), Some(Format::Plantuml));
+        assert_eq!(Format::from_path("model.drawio"), Some(Format::Drawio));
+        assert_eq!(
+            Format::from_path("model.excalidraw"),
+            Some(Format::Excalidraw)
+        );
+        assert_eq!(Format::from_path("model.txt"), Some(Format::Text));
+        assert_eq!(Format::from_path("model.json"), Some(Format::Json));
+        assert_eq!(Format::from_path("model"), None);
+        assert_eq!(Format::from_path("-"), None);
+    }
+}
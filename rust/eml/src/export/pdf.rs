@@ -0,0 +1,70 @@
+use super::parse_svg;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref};
+
+// each svg becomes a page of its own size, one svg pixel to a point
+pub fn render(pages: &[String]) -> Result<Vec<u8>, String> {
+    let trees = pages
+        .iter()
+        .map(|svg| parse_svg(svg))
+        .collect::<Result<Vec<_>, String>>()?;
+    let catalog_ref = Ref::new(1);
+    let page_tree_ref = Ref::new(2);
+    let mut next = Ref::new(3);
+    let mut pdf = Pdf::new();
+    let mut page_refs = Vec::with_capacity(trees.len());
+    let svg_name = Name(b"S1");
+    for tree in trees.iter() {
+        let page_ref = next;
+        let content_ref = Ref::new(page_ref.get() + 1);
+        let svg_ref = Ref::new(page_ref.get() + 2);
+        next = svg2pdf::convert_tree_into(tree, svg2pdf::Options::default(), &mut pdf, svg_ref);
+        let (width, height) = (tree.size.width(), tree.size.height());
+        let mut page = pdf.page(page_ref);
+        page.media_box(Rect::new(0f32, 0f32, width, height));
+        page.parent(page_tree_ref);
+        page.contents(content_ref);
+        page.resources().x_objects().pair(svg_name, svg_ref);
+        page.finish();
+        let mut content = Content::new();
+        content
+            .transform([width, 0f32, 0f32, height, 0f32, 0f32])
+            .x_object(svg_name);
+        pdf.stream(content_ref, &content.finish());
+        page_refs.push(page_ref);
+    }
+    pdf.catalog(catalog_ref).pages(page_tree_ref);
+    pdf.pages(page_tree_ref)
+        .count(page_refs.len() as i32)
+        .kids(page_refs);
+    Ok(pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn svg(width: u32) -> String {
+        format!("<svg xmlns='http://www.w3.org/2000/svg' width='{0}' height='20' viewBox='0 0 {0} 20'><rect width='{0}' height='20' fill='#ff0000' /></svg>", width)
+    }
+
+    fn count(haystack: &[u8], needle: &str) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|w| *w == needle.as_bytes())
+            .count()
+    }
+
+    #[test]
+    fn test_render_pdf() {
+        let pdf = render(&[svg(40), svg(60)]).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert_eq!(count(&pdf, "/Type /Page\n"), 2);
+        assert_eq!(count(&pdf, "/Count 2"), 1);
+        assert_eq!(count(&pdf, "/MediaBox [0 0 60 20]"), 1);
+    }
+
+    #[test]
+    fn test_render_pdf_invalid() {
+        assert!(render(&["<svg".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use super::{card_style, card_type, lane, node_ids, LANES};
+use crate::eventmodel::FlatModel;
+use crate::svg::Theme;
+
+fn alias(id: &str) -> String {
+    id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// inside a quoted description \n breaks the line, so literal quotes and
+// backslashes are written as unicode escapes
+fn quote(lines: &[String]) -> String {
+    let text = lines
+        .iter()
+        .map(|line| line.replace('\\', "<U+005C>").replace('"', "<U+0022>"))
+        .collect::<Vec<String>>()
+        .join("\\n");
+    format!("\"{}\"", text)
+}
+
+// a title is creole markup up to the end of the line, so everything but
+// letters, digits and spaces is written as a unicode escape
+fn literal(text: &str) -> String {
+    text.chars()
+        .map(|c| match c.is_alphanumeric() || c == ' ' {
+            true => c.to_string(),
+            false => format!("<U+{:04X}>", c as u32),
+        })
+        .collect()
+}
+
+// components stereotyped by card type, grouped into the three lanes
+pub fn render(model: &FlatModel, theme: &Theme, title: &str) -> String {
+    let ids = node_ids(model, alias);
+    let mut out = vec![
+        "@startuml".to_string(),
+        format!("title {}", literal(title)),
+        "left to right direction".to_string(),
+        format!("skinparam defaultFontName {}", theme.font_family),
+        format!("skinparam defaultFontSize {}", theme.font_size),
+        format!("skinparam ArrowColor {}", theme.arrow.stroke),
+        "skinparam component {".to_string(),
+    ];
+    let mut defined: Vec<&str> = Vec::new();
+    for card in model.cards.iter() {
+        let name = match card_type(&card.expression_type) {
+            Some(card_type) => card_type.name(),
+            None => continue,
+        };
+        if !defined.contains(&name) {
+            let style = card_style(theme, &card.expression_type);
+            out.push(format!("  BackgroundColor<<{}>> {}", name, style.fill));
+            out.push(format!("  BorderColor<<{}>> {}", name, style.stroke));
+            out.push(format!("  FontColor<<{}>> {}", name, style.text));
+            defined.push(name);
+        }
+    }
+    out.push("}".to_string());
+    for (index, name) in LANES.iter().enumerate() {
+        out.push(format!("rectangle \"{}\" as lane{} {{", name, index));
+        for card in model
+            .cards
+            .iter()
+            .filter(|c| lane(&c.expression_type) == Some(index))
+        {
+            let mut lines = vec![card.id.clone()];
+            if !card.lines.is_empty() {
+                lines.push("----".to_string());
+                lines.extend(card.lines.iter().cloned());
+            }
+            out.push(format!(
+                "  component {} as {} <<{}>>",
+                quote(&lines),
+                ids[card.id.as_str()],
+                card_type(&card.expression_type).map_or("card", |t| t.name()),
+            ));
+        }
+        out.push("}".to_string());
+    }
+    for (from, to) in model.flows.iter() {
+        out.push(format!("{} --> {}", ids[from.as_str()], ids[to.as_str()]));
+    }
+    out.push("@enduml".to_string());
+    out.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventmodel::flat::FlatCard;
+    use crate::eventmodel::ExpressionType;
+    use indoc::indoc;
+
+    #[test]
+    fn test_render_plantuml() {
+        let model = FlatModel {
+            cards: vec![
+                FlatCard {
+                    id: "AddTodo".to_string(),
+                    expression_type: ExpressionType::Command,
+                    lines: vec!["title: \"milk\"".to_string(), "path: a\\n".to_string()],
+                    context: None,
+                },
+                FlatCard {
+                    id: "Todos.TodoAdded".to_string(),
+                    expression_type: ExpressionType::Event,
+                    lines: vec![],
+                    context: Some("Todos".to_string()),
+                },
+            ],
+            flows: vec![("AddTodo".to_string(), "Todos.TodoAdded".to_string())],
+        };
+        let expected = indoc! {r##"
+            @startuml
+            title todo
+            left to right direction
+            skinparam defaultFontName monospace
+            skinparam defaultFontSize 16
+            skinparam ArrowColor black
+            skinparam component {
+              BackgroundColor<<command>> #60b3f7
+              BorderColor<<command>> black
+              FontColor<<command>> black
+              BackgroundColor<<event>> #f7a660
+              BorderColor<<event>> black
+              FontColor<<event>> black
+            }
+            rectangle "Forms & Jobs" as lane0 {
+            }
+            rectangle "Commands & Views" as lane1 {
+              component "AddTodo\n----\ntitle: <U+0022>milk<U+0022>\npath: a<U+005C>n" as AddTodo <<command>>
+            }
+            rectangle "Events" as lane2 {
+              component "Todos.TodoAdded" as Todos_TodoAdded <<event>>
+            }
+            AddTodo --> Todos_TodoAdded
+            @enduml
+        "##};
+        let observed = render(&model, &Theme::default(), "todo");
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_render_plantuml_ids_and_title() {
+        let card = |id: &str| FlatCard {
+            id: id.to_string(),
+            expression_type: ExpressionType::Event,
+            lines: vec![],
+            context: None,
+        };
+        let model = FlatModel {
+            cards: vec![card("A.B"), card("A_B")],
+            flows: vec![("A_B".to_string(), "A.B".to_string())],
+        };
+        let observed = render(&model, &Theme::default(), "a <b>\n@enduml");
+        assert!(observed.contains("title a <U+003C>b<U+003E><U+000A><U+0040>enduml\n"));
+        assert!(observed.contains("component \"A.B\" as A_B <<event>>"));
+        assert!(observed.contains("component \"A_B\" as A_B_2 <<event>>"));
+        assert!(observed.contains("\nA_B_2 --> A_B\n"));
+    }
+}
@@ -0,0 +1,51 @@
+use super::parse_svg;
+use resvg::tiny_skia::{Pixmap, Transform};
+
+// rasterise an svg document, scale 2 gives twice the pixels on each side
+pub fn render(svg: &str, scale: f32) -> Result<Vec<u8>, String> {
+    let tree = parse_svg(svg)?;
+    let size = tree
+        .size
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or(format!("cannot scale the image by {}", scale))?;
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or(format!(
+        "cannot allocate a {}x{} image",
+        size.width(),
+        size.height()
+    ))?;
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = "<svg xmlns='http://www.w3.org/2000/svg' width='40' height='20' viewBox='0 0 40 20'><rect x='0' y='0' width='40' height='20' fill='#ff0000' /></svg>";
+
+    #[test]
+    fn test_render_png() {
+        let png = render(SVG, 1f32).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (40, 20));
+        let pixel = pixmap.pixel(20, 10).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_render_png_scaled() {
+        let png = render(SVG, 2.5).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (100, 50));
+    }
+
+    #[test]
+    fn test_render_png_invalid() {
+        assert!(render("<svg", 1f32).is_err());
+    }
+}
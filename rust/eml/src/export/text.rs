@@ -0,0 +1,309 @@
+use super::LANES;
+use crate::svg::{row, shares_column, Card, CardStyle, CardType, SvgDocument};
+
+// connections of an arrow cell, merged into one box drawing character
+const UP: u8 = 1;
+const RIGHT: u8 = 2;
+const DOWN: u8 = 4;
+const LEFT: u8 = 8;
+
+// spaces between columns, the arrows turn in the middle of them
+const GAP: usize = 6;
+// a separator, a channel, the three lines of a box and another channel
+const BAND: usize = 6;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    Empty,
+    Text(char, Option<usize>),
+    Line(u8),
+    Head(char),
+}
+
+fn junction(mask: u8) -> char {
+    match mask {
+        m if m == UP | DOWN || m == UP || m == DOWN => '│',
+        m if m == LEFT | RIGHT || m == LEFT || m == RIGHT => '─',
+        m if m == DOWN | RIGHT => '┌',
+        m if m == DOWN | LEFT => '┐',
+        m if m == UP | RIGHT => '└',
+        m if m == UP | LEFT => '┘',
+        m if m == UP | DOWN | RIGHT => '├',
+        m if m == UP | DOWN | LEFT => '┤',
+        m if m == LEFT | RIGHT | DOWN => '┬',
+        m if m == LEFT | RIGHT | UP => '┴',
+        0 => ' ',
+        _ => '┼',
+    }
+}
+
+// theme colours are hex or one of the two names the built-in themes use
+fn rgb(color: &str) -> Option<(u8, u8, u8)> {
+    match color {
+        "black" => return Some((0, 0, 0)),
+        "white" => return Some((255, 255, 255)),
+        _ => {}
+    }
+    let hex = color.strip_prefix('#').filter(|h| h.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+// a card's fill as the ansi background and its text colour as the foreground
+fn ansi(style: &CardStyle) -> String {
+    let mut escape = String::new();
+    if let Some((r, g, b)) = rgb(&style.fill) {
+        escape.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b));
+    }
+    if let Some((r, g, b)) = rgb(&style.text) {
+        escape.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+    }
+    escape
+}
+
+struct Placed {
+    x0: usize,
+    x1: usize,
+    y0: usize,
+    y1: usize,
+    column: usize,
+    row: usize,
+}
+
+struct Canvas {
+    cells: Vec<Vec<Cell>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Canvas {
+            cells: vec![vec![Cell::Empty; width]; height],
+        }
+    }
+
+    fn text(&mut self, x: usize, y: usize, text: &str, style: Option<usize>) {
+        for (i, c) in text.chars().enumerate() {
+            self.cells[y][x + i] = Cell::Text(c, style);
+        }
+    }
+
+    fn connect(&mut self, x: usize, y: usize, mask: u8) {
+        self.cells[y][x] = match self.cells[y][x] {
+            Cell::Line(existing) => Cell::Line(existing | mask),
+            Cell::Head(head) => Cell::Head(head),
+            _ => Cell::Line(mask),
+        };
+    }
+
+    // an axis aligned run of line cells from one point to another
+    fn segment(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let (mut x, mut y) = from;
+        while (x, y) != to {
+            let (dir, back, next) = if x < to.0 {
+                (RIGHT, LEFT, (x + 1, y))
+            } else if x > to.0 {
+                (LEFT, RIGHT, (x - 1, y))
+            } else if y < to.1 {
+                (DOWN, UP, (x, y + 1))
+            } else {
+                (UP, DOWN, (x, y - 1))
+            };
+            self.connect(x, y, dir);
+            self.connect(next.0, next.1, back);
+            (x, y) = next;
+        }
+    }
+
+    fn render(&self, styles: &[String]) -> String {
+        let reset = "\x1b[0m";
+        self.cells
+            .iter()
+            .map(|line| {
+                let mut out = String::new();
+                let mut current: Option<usize> = None;
+                for cell in line {
+                    let (c, style) = match cell {
+                        Cell::Empty => (' ', None),
+                        Cell::Text(c, style) => (*c, *style),
+                        Cell::Line(mask) => (junction(*mask), None),
+                        Cell::Head(c) => (*c, None),
+                    };
+                    let style = style.filter(|s| !styles[*s].is_empty());
+                    if style != current {
+                        if current.is_some() {
+                            out.push_str(reset);
+                        }
+                        if let Some(s) = style {
+                            out.push_str(&styles[s]);
+                        }
+                        current = style;
+                    }
+                    out.push(c);
+                }
+                if current.is_some() {
+                    out.push_str(reset);
+                }
+                out.trim_end().to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+            .trim_end()
+            .to_string()
+            + "\n"
+    }
+}
+
+// the timeline as box drawing art, one band per lane
+pub fn render(doc: &SvgDocument, color: bool) -> String {
+    let columns = doc.layout_columns();
+    let count = columns.iter().max().map_or(0, |c| c + 1);
+    let box_width = |card: &Card| card.id.chars().count() + 4;
+    let mut widths = vec![6usize; count];
+    for (card, &column) in doc.cards.iter().zip(columns.iter()) {
+        widths[column] = widths[column].max(box_width(card));
+    }
+    let mut column_x = Vec::with_capacity(count);
+    // lane labels sit in a gutter left of the first column
+    let margin = LANES.iter().map(|l| l.len()).max().unwrap_or(0) + GAP;
+    let mut x = margin;
+    for width in widths.iter() {
+        column_x.push(x);
+        x += width + GAP;
+    }
+    let width = x - GAP;
+    let mut canvas = Canvas::new(width, BAND * LANES.len());
+    for (index, name) in LANES.iter().enumerate() {
+        canvas.text(0, index * BAND, &"┄".repeat(width), None);
+        canvas.text(0, index * BAND + 3, name, None);
+    }
+    // card styles are indexed by card type
+    let types = [
+        CardType::Form,
+        CardType::Job,
+        CardType::Command,
+        CardType::Event,
+        CardType::View,
+    ];
+    let styles = types
+        .iter()
+        .map(|t| match color {
+            true => ansi(doc.theme.card_style(t)),
+            false => String::new(),
+        })
+        .collect::<Vec<String>>();
+    let placed = doc
+        .cards
+        .iter()
+        .zip(columns.iter())
+        .map(|(card, &column)| {
+            let lane = row(&card.card_type);
+            let bw = box_width(card);
+            let x0 = column_x[column] + (widths[column] - bw) / 2;
+            let y0 = lane * BAND + 2;
+            let style = types.iter().position(|t| *t == card.card_type);
+            canvas.text(x0, y0, &format!("┌{}┐", "─".repeat(bw - 2)), style);
+            canvas.text(x0, y0 + 1, &format!("│ {} │", card.id), style);
+            canvas.text(x0, y0 + 2, &format!("└{}┘", "─".repeat(bw - 2)), style);
+            Placed {
+                x0,
+                x1: x0 + bw - 1,
+                y0,
+                y1: y0 + 2,
+                column,
+                row: lane,
+            }
+        })
+        .collect::<Vec<Placed>>();
+    let centre = |p: &Placed| column_x[p.column] + widths[p.column] / 2;
+    for arrow in doc.arrows.iter() {
+        let find = |id: &str| doc.cards.iter().position(|c| c.id == id);
+        let (from, to) = match (find(&arrow.begin_at.id), find(&arrow.end_at.id)) {
+            (Some(from), Some(to)) => (&placed[from], &placed[to]),
+            _ => continue,
+        };
+        let stacked = from.column == to.column
+            && to.row == from.row + 1
+            && shares_column(&arrow.begin_at.card_type, &arrow.end_at.card_type);
+        if stacked {
+            let x = centre(from);
+            canvas.segment((x, from.y1 + 1), (x, to.y0 - 1));
+            canvas.connect(x, from.y1 + 1, UP);
+            canvas.cells[to.y0 - 1][x] = Cell::Head('▼');
+            continue;
+        }
+        // leave the card through the channel on the side facing the target,
+        // clear of any stacked arrow at its centre, turn in the gap left of
+        // the target and enter it from the left
+        let (channel, stub) = if to.row < from.row {
+            (from.y0 - 1, DOWN)
+        } else {
+            (from.y1 + 1, UP)
+        };
+        let gap = column_x[to.column] - GAP / 2;
+        let middle = to.y0 + 1;
+        let x = from.x1 - 2;
+        canvas.segment((x, channel), (gap, channel));
+        canvas.segment((gap, channel), (gap, middle));
+        canvas.segment((gap, middle), (to.x0 - 1, middle));
+        canvas.connect(x, channel, stub);
+        canvas.cells[middle][to.x0 - 1] = Cell::Head('▶');
+    }
+    canvas.render(&styles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::{Arrow, Theme};
+    use indoc::indoc;
+
+    fn document() -> SvgDocument {
+        let mut doc = SvgDocument::new(Theme::default());
+        let cards = [
+            ("TodoForm", CardType::Form),
+            ("AddTodo", CardType::Command),
+            ("TodoAdded", CardType::Event),
+            ("Todos", CardType::View),
+        ];
+        for (id, card_type) in cards {
+            doc.cards.push(Card::new(id.to_string(), card_type, vec![]));
+        }
+        for (from, to) in [(0, 1), (1, 2), (2, 3)] {
+            doc.arrows
+                .push(Arrow::new(doc.cards[from].clone(), doc.cards[to].clone()));
+        }
+        doc
+    }
+
+    #[test]
+    fn test_render_text() {
+        let expected = indoc! {"
+        ┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄
+
+                              ┌──────────┐
+        Forms & Jobs          │ TodoForm │
+                              └──────────┘
+                                    │
+        ┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄│┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄
+                                    ▼
+                               ┌─────────┐       ┌───────┐
+        Commands & Views       │ AddTodo │    ┌─▶│ Todos │
+                               └─────────┘    │  └───────┘
+                                    │         │
+        ┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄┄│┄┄┄┄┄┄┄┄┄│┄┄┄┄┄┄┄┄┄┄┄
+                                    ▼   ┌─────┘
+                              ┌───────────┐
+        Events                │ TodoAdded │
+                              └───────────┘
+        "};
+        let observed = render(&document(), false);
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_render_text_color() {
+        let observed = render(&document(), true);
+        assert!(observed.contains("\x1b[48;2;96;179;247m\x1b[38;2;0;0;0m│ AddTodo │\x1b[0m"));
+        assert!(!render(&document(), false).contains('\x1b'));
+    }
+}
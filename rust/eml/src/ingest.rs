@@ -1,5 +1,6 @@
 use super::eventmodel::*;
-use super::svg::{Arrow, Card, CardType, SvgDocument};
+use super::svg::{Arrow, Card, CardType, ContextFrame, SvgDocument, TextOverflow};
+use super::utils::{content_hash, unique_id};
 
 fn ingest_fields_card(card_type: CardType, id: ExpressionId, fields: Vec<Field>) -> Card {
     Card::new(
@@ -17,16 +18,41 @@ fn ingest_fields_card(card_type: CardType, id: ExpressionId, fields: Vec<Field>)
 }
 
 fn ingest_table_card(card_type: CardType, id: ExpressionId, tbl: Vec<String>) -> Card {
-    Card::new(id.0.clone(), card_type, tbl)
+    // wrapping would break the table columns apart
+    let mut card = Card::new(id.0.clone(), card_type, tbl);
+    card.text_overflow = TextOverflow::Truncate;
+    card
+}
+
+fn scoped(context: Option<&ExpressionId>, id: ExpressionId) -> ExpressionId {
+    match context {
+        Some(ctx) => id.qualified(ctx),
+        None => id,
+    }
 }
 
 // impl this here because it relies on eventmodel stuff
 impl SvgDocument {
-    pub fn get_card(&self, id: ExpressionId) -> Card {
-        self.cards.iter().find(|c| c.id == id.0).unwrap().clone()
+    // the card a reference finds, resolved the same way as for every
+    // other output
+    pub fn get_card(
+        &self,
+        context: Option<&ExpressionId>,
+        id: &ExpressionId,
+    ) -> Result<Card, String> {
+        let id = id.resolve(context, |id| self.cards.iter().any(|c| c.id == id))?;
+        let card = self.cards.iter().find(|c| c.id == id.0);
+        card.cloned().ok_or(format!("unknown id {}", id.0))
     }
 
-    pub fn ingest_card(&mut self, card_type: CardType, id: ExpressionId, body: Body) {
+    pub fn ingest_card(
+        &mut self,
+        context: Option<&ExpressionId>,
+        card_type: CardType,
+        id: ExpressionId,
+        body: Body,
+    ) -> Result<(), String> {
+        let id = scoped(context, id);
         match body {
             Body::FieldBody(fields) => {
                 let card = ingest_fields_card(card_type, id, fields);
@@ -37,35 +63,93 @@ impl SvgDocument {
                 self.cards.push(card);
             }
             Body::UseBody(exprid) => {
-                let mut card = self.get_card(exprid);
+                let mut card = self.get_card(context, &exprid)?;
                 card.id = id.0;
                 card.card_type = card_type;
                 self.cards.push(card);
             }
         }
+        Ok(())
+    }
+
+    fn ingest_flow(
+        &mut self,
+        context: Option<&ExpressionId>,
+        expr_ids: Vec<ExpressionId>,
+    ) -> Result<(), String> {
+        let cards = expr_ids
+            .iter()
+            .map(|id| self.get_card(context, id))
+            .collect::<Result<Vec<Card>, String>>()?;
+        for pair in cards.windows(2) {
+            let mut arrow = Arrow::new(pair[0].clone(), pair[1].clone());
+            // the same flow drawn twice still needs distinct ids
+            let taken = self
+                .arrows
+                .iter()
+                .map(|a| a.id.as_str())
+                .collect::<Vec<&str>>();
+            arrow.id = unique_id(arrow.id, &taken);
+            self.arrows.push(arrow);
+        }
+        Ok(())
     }
 
-    pub fn ingest_expressions(&mut self, expressions: Vec<Expression>) {
+    fn ingest_scoped(
+        &mut self,
+        context: Option<&ExpressionId>,
+        expressions: Vec<Expression>,
+    ) -> Result<(), String> {
         for expr in expressions {
             match expr {
-                Expression::Form(id, body) => self.ingest_card(CardType::Form, id, body),
-                Expression::Job(id, body) => self.ingest_card(CardType::Job, id, body),
-                Expression::Command(id, body) => self.ingest_card(CardType::Command, id, body),
-                Expression::Event(id, body) => self.ingest_card(CardType::Event, id, body),
-                Expression::View(id, body) => self.ingest_card(CardType::View, id, body),
-                Expression::Flow(_, expr_ids) => {
-                    let (_, from_ids) = expr_ids.split_last().unwrap();
-                    let (_, to_ids) = expr_ids.split_first().unwrap();
-                    for (from, to) in from_ids.iter().zip(to_ids.iter()) {
-                        let arrow = Arrow::new(
-                            self.cards.iter().find(|c| c.id == from.0).unwrap().clone(),
-                            self.cards.iter().find(|c| c.id == to.0).unwrap().clone(),
-                        );
-                        self.arrows.push(arrow);
+                Expression::Form(id, body) => {
+                    self.ingest_card(context, CardType::Form, id, body)?
+                }
+                Expression::Job(id, body) => self.ingest_card(context, CardType::Job, id, body)?,
+                Expression::Command(id, body) => {
+                    self.ingest_card(context, CardType::Command, id, body)?
+                }
+                Expression::Event(id, body) => {
+                    self.ingest_card(context, CardType::Event, id, body)?
+                }
+                Expression::View(id, body) => {
+                    self.ingest_card(context, CardType::View, id, body)?
+                }
+                Expression::Flow(_, expr_ids) => self.ingest_flow(context, expr_ids)?,
+                Expression::Context(id, exprs) => {
+                    let id = scoped(context, id);
+                    let first = self.cards.len();
+                    self.ingest_scoped(Some(&id), exprs)?;
+                    let card_ids = self.cards[first..]
+                        .iter()
+                        .map(|c| c.id.clone())
+                        .collect::<Vec<String>>();
+                    self.contexts.push(ContextFrame::new(id.0, card_ids));
+                }
+                // a `# ----` rule heads a slice
+                Expression::Comment(text) => {
+                    let rule = !text.trim().is_empty() && text.trim().chars().all(|c| c == '-');
+                    if rule && self.slices.last() != Some(&self.cards.len()) {
+                        self.slices.push(self.cards.len());
                     }
                 }
             }
         }
+        Ok(())
+    }
+
+    pub fn ingest_expressions(&mut self, expressions: Vec<Expression>) -> Result<(), String> {
+        self.ingest_scoped(None, expressions)?;
+        let content = self
+            .cards
+            .iter()
+            .flat_map(|c| std::iter::once(&c.id).chain(c.text_lines.iter()))
+            .chain(self.arrows.iter().map(|a| &a.id))
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        self.id = format!("eml-{}", content_hash(&content));
+        Ok(())
     }
 }
 
@@ -73,19 +157,12 @@ impl SvgDocument {
 mod tests {
     use super::*;
     use crate::svg::*;
-    use crate::utils::newid;
+    use indoc::indoc;
 
     #[test]
     fn test_ingest() {
-        let expected = "<svg xmlns='http://www.w3.org/2000/svg' width='1650' height='1350'><defs><marker id='triangle' viewBox='0 0 10 10' refX='0' refY='5' markerUnits='strokeWidth' markerWidth='10' markerHeight='8' orient='auto'><path d='M 0 0 L 10 5 L 0 10 z' /></marker></defs><g id='swimlane' stroke='black' stroke-width='3' ><line id='swimlane_top' x1='0' y1='450' x2='1650' y2='450' /><line id='swimlane_bottom' x1='0' y1='900' x2='1650' y2='900' /></g><path d='M 450 225 Q 750 225 750 585' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' /><path d='M 750 750 Q 750 1275 1035 1275' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' /><rect id='AddTodoForm' stroke='black' stroke-width='2' x='150' y='150' height='150' width='300' fill='#ffffff' /><text transform='translate(162.5 162.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' ><tspan x='0' dy='1rem' font-size='larger' font-weight='bold'>AddTodoForm</tspan><tspan x='0' dy='1rem'>==========</tspan><tspan x='0' dy='1rem'>key: todo1</tspan><tspan x='0' dy='1rem'>description: Wake up</tspan></text><rect id='AddTodo' stroke='black' stroke-width='2' x='600' y='600' height='150' width='300' fill='#60b3f7' /><text transform='translate(612.5 612.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' ><tspan x='0' dy='1rem' font-size='larger' font-weight='bold'>AddTodo</tspan><tspan x='0' dy='1rem'>==========</tspan><tspan x='0' dy='1rem'>key: todo1</tspan><tspan x='0' dy='1rem'>description: Wake up</tspan></text><rect id='TodoAdded' stroke='black' stroke-width='2' x='1050' y='1200' height='150' width='300' fill='#f7a660' /><text transform='translate(1062.5 1212.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' ><tspan x='0' dy='1rem' font-size='larger' font-weight='bold'>TodoAdded</tspan><tspan x='0' dy='1rem'>==========</tspan><tspan x='0' dy='1rem'>key: todo1</tspan><tspan x='0' dy='1rem'>description: Wake up</tspan></text></svg>".to_string();
-        let mut doc = SvgDocument {
-            id: newid(),
-            width: 1000f64,
-            height: 1000f64,
-            cards: Vec::new(),
-            arrows: Vec::new(),
-            swimlane: Swimlane::new(),
-        };
+        let expected = "<svg xmlns='http://www.w3.org/2000/svg' id='eml-9152efd047ce8c99' width='750' height='1350' viewBox='0 0 750 1350' role='img' aria-labelledby='eml_title eml_desc'><title id='eml_title'>Event model</title><desc id='eml_desc'>An event model of 1 form, 1 command and 1 event connected by 2 flows.</desc><defs><marker id='triangle' viewBox='0 0 10 10' refX='0' refY='5' markerUnits='strokeWidth' markerWidth='10' markerHeight='8' orient='auto'><path d='M 0 0 L 10 5 L 0 10 z' fill='black' /></marker></defs><g id='swimlane' stroke='black' stroke-width='3' ><line id='swimlane_top' x1='0' y1='450' x2='750' y2='450' /><line id='swimlane_bottom' x1='0' y1='900' x2='750' y2='900' /></g><path id='flow-AddTodoForm-AddTodo' d='M 300 300 L 300 585' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' role='img' aria-label='flow from AddTodoForm to AddTodo' /><path id='flow-AddTodo-TodoAdded' d='M 300 750 L 300 1035' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' role='img' aria-label='flow from AddTodo to TodoAdded' /><g role='img' aria-label='form AddTodoForm'><title>AddTodoForm\nkey: todo1\ndescription: Wake up</title><rect id='card-AddTodoForm' stroke='black' stroke-width='2' x='150' y='150' height='150' width='300' fill='#ffffff' /><text transform='translate(162.5 162.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' font-size='16' fill='black' ><tspan x='0' dy='16' font-size='19.2' font-weight='bold'>AddTodoForm</tspan><tspan x='0' dy='16'>==========</tspan><tspan x='0' dy='16'>key: todo1</tspan><tspan x='0' dy='16'>description: Wake up</tspan></text></g><g role='img' aria-label='command AddTodo'><title>AddTodo\nkey: todo1\ndescription: Wake up</title><rect id='card-AddTodo' stroke='black' stroke-width='2' x='150' y='600' height='150' width='300' fill='#60b3f7' /><text transform='translate(162.5 612.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' font-size='16' fill='black' ><tspan x='0' dy='16' font-size='19.2' font-weight='bold'>AddTodo</tspan><tspan x='0' dy='16'>==========</tspan><tspan x='0' dy='16'>key: todo1</tspan><tspan x='0' dy='16'>description: Wake up</tspan></text></g><g role='img' aria-label='event TodoAdded'><title>TodoAdded\nkey: todo1\ndescription: Wake up</title><rect id='card-TodoAdded' stroke='black' stroke-width='2' x='150' y='1050' height='150' width='300' fill='#f7a660' /><text transform='translate(162.5 1062.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' font-size='16' fill='black' ><tspan x='0' dy='16' font-size='19.2' font-weight='bold'>TodoAdded</tspan><tspan x='0' dy='16'>==========</tspan><tspan x='0' dy='16'>key: todo1</tspan><tspan x='0' dy='16'>description: Wake up</tspan></text></g></svg>".to_string();
+        let mut doc = SvgDocument::new(Theme::default());
         let model = EventModel {
             expressions: vec![
                 Expression::Form(
@@ -128,7 +205,7 @@ mod tests {
                     ]),
                 ),
                 Expression::Flow(
-                    ExpressionId(newid()),
+                    ExpressionId("flow-AddTodoForm-AddTodo-TodoAdded".to_string()),
                     vec![
                         ExpressionId("AddTodoForm".to_string()),
                         ExpressionId("AddTodo".to_string()),
@@ -137,14 +214,163 @@ mod tests {
                 ),
             ],
         };
-        doc.ingest_expressions(model.expressions);
-        let config = SvgConfig {
-            pad: 150f64,
-            card_width: 300f64,
-            card_height: 150f64,
-        };
-        doc.set_dimensions(&config);
+        doc.ingest_expressions(model.expressions).unwrap();
+        let config = SvgConfig::default();
+        doc.set_dimensions(&config).unwrap();
         let observed = doc.render();
         assert_eq!(observed, expected);
     }
+
+    #[test]
+    fn test_ingest_context() {
+        let mut doc = SvgDocument::new(Theme::default());
+        let model = EventModel {
+            expressions: vec![
+                Expression::Command(
+                    ExpressionId("AddAccount".to_string()),
+                    Body::FieldBody(vec![]),
+                ),
+                Expression::Context(
+                    ExpressionId("Billing".to_string()),
+                    vec![
+                        Expression::Command(
+                            ExpressionId("AddAccount".to_string()),
+                            Body::FieldBody(vec![]),
+                        ),
+                        Expression::Event(
+                            ExpressionId("AccountAdded".to_string()),
+                            Body::UseBody(ExpressionId("AddAccount".to_string())),
+                        ),
+                        Expression::Flow(
+                            ExpressionId("flow-AddAccount-AccountAdded".to_string()),
+                            vec![
+                                ExpressionId("AddAccount".to_string()),
+                                ExpressionId("AccountAdded".to_string()),
+                            ],
+                        ),
+                    ],
+                ),
+                Expression::Flow(
+                    ExpressionId("flow-AddAccount-Billing.AccountAdded".to_string()),
+                    vec![
+                        ExpressionId("AddAccount".to_string()),
+                        ExpressionId("Billing.AccountAdded".to_string()),
+                    ],
+                ),
+            ],
+        };
+        doc.ingest_expressions(model.expressions).unwrap();
+        let ids = doc
+            .cards
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            ids,
+            vec!["AddAccount", "Billing.AddAccount", "Billing.AccountAdded"]
+        );
+        let arrows = doc
+            .arrows
+            .iter()
+            .map(|a| (a.begin_at.id.as_str(), a.end_at.id.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            arrows,
+            vec![
+                ("Billing.AddAccount", "Billing.AccountAdded"),
+                ("AddAccount", "Billing.AccountAdded"),
+            ]
+        );
+        assert_eq!(doc.contexts.len(), 1);
+        assert_eq!(
+            doc.contexts[0].card_ids,
+            vec!["Billing.AddAccount", "Billing.AccountAdded"]
+        );
+        let config = SvgConfig::default();
+        doc.set_dimensions(&config).unwrap();
+        let frame = &doc.contexts[0];
+        assert_eq!((frame.origin.x, frame.origin.y), (562.5, 562.5));
+        assert_eq!((frame.width, frame.height), (375f64, 675f64));
+    }
+
+    #[test]
+    fn test_ingest_slices() {
+        let model = crate::parse::parse(indoc! {"
+            # eml: 0.0.1
+            # Customer Entry
+            # --------------
+            form CustomerForm { Name: John }
+            command AddCustomer { use CustomerForm }
+            # not a heading
+            event CustomerAdded { use AddCustomer }
+            # Account Addition
+            # --------------
+            view AccountsToAdd { use CustomerForm }
+            job ProcessAccountsToAdd { use CustomerForm }
+            flow { CustomerForm => AddCustomer => CustomerAdded => AccountsToAdd }
+            flow { AccountsToAdd => ProcessAccountsToAdd }
+        "})
+        .unwrap();
+        let mut doc = SvgDocument::new(Theme::default());
+        doc.ingest_expressions(model.expressions).unwrap();
+        assert_eq!(doc.slices, vec![0, 3]);
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        // the view and the job it feeds stay on one page
+        let pages = doc.paginate(1200f64);
+        let view = &doc.cards[3];
+        let job = &doc.cards[4];
+        let page = pages
+            .iter()
+            .find(|(left, right)| *left <= view.origin.x && view.origin.x < *right)
+            .unwrap();
+        assert!(page.0 <= job.origin.x && job.origin.x + job.width <= page.1);
+    }
+
+    #[test]
+    fn test_ingest_dangling() {
+        for input in [
+            "# eml: 0.0.1\ncommand A { use Nope }\n",
+            "# eml: 0.0.1\ncommand A { }\nflow { A => Nope }\n",
+        ] {
+            let model = crate::parse::parse(input).unwrap();
+            let mut doc = SvgDocument::new(Theme::default());
+            let observed = doc.ingest_expressions(model.expressions).unwrap_err();
+            assert_eq!(observed, "unknown id Nope");
+        }
+    }
+
+    #[test]
+    fn test_ingest_deterministic() {
+        let input = indoc! {"
+            # eml: 0.0.1
+            form AddTodoForm { Name: str }
+            command AddTodo { use AddTodoForm }
+            event TodoAdded { use AddTodo }
+            flow { AddTodoForm => AddTodo => TodoAdded }
+            flow { AddTodo => TodoAdded }
+        "};
+        let render = || {
+            let mut doc = SvgDocument::new(Theme::default());
+            doc.ingest_expressions(crate::parse::parse(input).unwrap().expressions)
+                .unwrap();
+            let ids = doc
+                .arrows
+                .iter()
+                .map(|a| a.id.clone())
+                .collect::<Vec<String>>();
+            doc.set_dimensions(&SvgConfig::default()).unwrap();
+            (ids, doc.render())
+        };
+        let (ids, first) = render();
+        let (_, second) = render();
+        assert_eq!(
+            ids,
+            vec![
+                "flow-AddTodoForm-AddTodo",
+                "flow-AddTodo-TodoAdded",
+                "flow-AddTodo-TodoAdded-2"
+            ]
+        );
+        assert_eq!(first, second);
+    }
 }
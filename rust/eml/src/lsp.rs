@@ -0,0 +1,666 @@
+use super::cst::{self, Element, Node, SyntaxKind};
+use super::parse::partial;
+use super::rename;
+use super::symbols::Symbols;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{DidChangeTextDocument, DidCloseTextDocument};
+use lsp_types::notification::{DidOpenTextDocument, PublishDiagnostics};
+use lsp_types::request::{Completion, DocumentSymbolRequest, GotoDefinition};
+use lsp_types::request::{HoverRequest, References, Rename};
+use lsp_types::{notification, request};
+use lsp_types::{CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams};
+use lsp_types::{Diagnostic, DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams};
+use lsp_types::{GotoDefinitionParams, Hover, HoverContents, HoverParams};
+use lsp_types::{Location, MarkupContent, MarkupKind, OneOf, Position};
+use lsp_types::{PublishDiagnosticsParams, ReferenceParams, RenameParams};
+use lsp_types::{ServerCapabilities, SymbolKind, TextEdit, WorkspaceEdit};
+use lsp_types::{TextDocumentPositionParams, TextDocumentSyncCapability};
+use lsp_types::{TextDocumentSyncKind, Uri};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::ops::Range;
+
+// lsp positions count utf-16 code units within a line
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() as u32;
+    Position::new(line, before[start..].encode_utf16().count() as u32)
+}
+
+fn offset(text: &str, position: Position) -> usize {
+    let start = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum::<usize>();
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= position.character as usize || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, span: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range::new(position(text, span.start), position(text, span.end))
+}
+
+fn symbol_kind(keyword: &str) -> SymbolKind {
+    match keyword {
+        "form" => SymbolKind::STRUCT,
+        "job" => SymbolKind::FUNCTION,
+        "command" => SymbolKind::METHOD,
+        "event" => SymbolKind::EVENT,
+        "view" => SymbolKind::INTERFACE,
+        "flow" => SymbolKind::OPERATOR,
+        _ => SymbolKind::NAMESPACE,
+    }
+}
+
+fn completion_kind(keyword: &str) -> CompletionItemKind {
+    match keyword {
+        "form" => CompletionItemKind::STRUCT,
+        "job" => CompletionItemKind::FUNCTION,
+        "command" => CompletionItemKind::METHOD,
+        "event" => CompletionItemKind::EVENT,
+        _ => CompletionItemKind::INTERFACE,
+    }
+}
+
+// a parsed document with the spans of its ids
+struct Document {
+    text: String,
+    file: Node,
+    symbols: Symbols,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let file = cst::parse(&text);
+        let symbols = Symbols::new(&file);
+        Document {
+            text,
+            file,
+            symbols,
+        }
+    }
+
+    fn range(&self, span: Range<usize>) -> lsp_types::Range {
+        range(&self.text, span)
+    }
+
+    // parse errors, or the problems of a model that parsed
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let line_end = |start: usize| {
+            let rest = &self.text[start..];
+            start + rest.find(['\r', '\n']).unwrap_or(rest.len())
+        };
+        let mut problems = Vec::new();
+        if self.file.token(SyntaxKind::Header).is_none() {
+            problems.push((0..line_end(0), "bad version".to_string()));
+        }
+        for child in self.file.children.iter() {
+            if let Element::Token(error) = child {
+                if error.kind == SyntaxKind::Error {
+                    let start = error.offset + error.text.len() - error.text.trim_start().len();
+                    problems.push((start..line_end(start), "unexpected input".to_string()));
+                }
+            }
+        }
+        if problems.is_empty() {
+            problems = self.symbols.problems();
+        }
+        problems
+            .into_iter()
+            .map(|(span, message)| Diagnostic {
+                range: self.range(span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("eml".to_string()),
+                message,
+                ..Diagnostic::default()
+            })
+            .collect()
+    }
+
+    fn definition(&self, offset: usize) -> Option<Range<usize>> {
+        let id = self.symbols.at(offset)?;
+        Some(self.symbols.definition(id)?.name.clone())
+    }
+
+    fn references(&self, offset: usize, declaration: bool) -> Vec<Range<usize>> {
+        let Some(id) = self.symbols.at(offset) else {
+            return Vec::new();
+        };
+        let definitions = self
+            .symbols
+            .definition(id)
+            .filter(|_| declaration)
+            .map(|d| d.name.clone());
+        let references = self.symbols.references_to(id).map(|r| r.span.clone());
+        definitions.into_iter().chain(references).collect()
+    }
+
+    // the spans that renaming the card at an offset replaces
+    fn rename(&self, offset: usize, new: &str) -> Result<Vec<Range<usize>>, String> {
+        let id = self.symbols.at(offset).ok_or("no card to rename here")?;
+        rename::spans(&self.text, id, new)
+    }
+
+    // the card with its fields, following use
+    fn hover(&self, offset: usize) -> Option<String> {
+        let id = self.symbols.at(offset)?;
+        let definition = self.symbols.definition(id)?;
+        let flat = partial(&self.file).flatten().ok()?;
+        let card = flat.cards.iter().find(|c| c.id == id.0)?;
+        let heading = format!("{} {}", definition.keyword, id.0);
+        Some(match card.lines.iter().all(|l| l.trim().is_empty()) {
+            true => heading,
+            false => format!("{}\n\n```\n{}\n```", heading, card.lines.join("\n").trim()),
+        })
+    }
+
+    // ids after => and use, bare for cards in the same context
+    fn completion(&self, offset: usize) -> Vec<CompletionItem> {
+        let start = self.text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let before = self.text[start..offset]
+            .trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '.')
+            .trim_end();
+        let word = before.rsplit(|c: char| !c.is_ascii_alphabetic()).next();
+        if !before.ends_with("=>") && word != Some("use") {
+            return Vec::new();
+        }
+        let context = self
+            .symbols
+            .definitions
+            .iter()
+            .find(|d| d.keyword == "context" && d.span.start < offset && offset < d.span.end)
+            .map(|d| &d.id);
+        let mut labels = Vec::new();
+        let mut items = Vec::new();
+        for card in self.symbols.cards() {
+            let label = match context.is_some() && card.context.as_ref() == context {
+                true => self.text[card.name.clone()].to_string(),
+                false => card.id.0.clone(),
+            };
+            if labels.contains(&label) {
+                continue;
+            }
+            labels.push(label.clone());
+            items.push(CompletionItem {
+                label,
+                kind: Some(completion_kind(&card.keyword)),
+                detail: Some(card.keyword.clone()),
+                ..CompletionItem::default()
+            });
+        }
+        items
+    }
+
+    // the cards, flows and contexts grouped by type
+    #[allow(deprecated)]
+    fn symbols(&self) -> Vec<DocumentSymbol> {
+        let symbol = |name: String, kind: SymbolKind, span: Range<usize>, name_span, children| {
+            DocumentSymbol {
+                name,
+                detail: None,
+                kind,
+                tags: None,
+                deprecated: None,
+                range: self.range(span),
+                selection_range: self.range(name_span),
+                children,
+            }
+        };
+        let definitions = |keyword: &str| {
+            self.symbols
+                .definitions
+                .iter()
+                .filter(|d| d.keyword == keyword)
+                .map(|d| (d.id.0.clone(), d.span.clone(), d.name.clone()))
+                .collect::<Vec<(String, Range<usize>, Range<usize>)>>()
+        };
+        let mut groups = Vec::new();
+        for keyword in ["form", "job", "command", "event", "view", "flow", "context"] {
+            let members = match keyword {
+                "flow" => self
+                    .symbols
+                    .flows
+                    .iter()
+                    .map(|(name, span)| (name.clone(), span.clone(), span.clone()))
+                    .collect(),
+                _ => definitions(keyword),
+            };
+            let (Some(first), Some(last)) = (members.first(), members.last()) else {
+                continue;
+            };
+            let span = first.1.start..last.1.end;
+            let name_span = first.2.clone();
+            let kind = symbol_kind(keyword);
+            let children = members
+                .iter()
+                .map(|(name, span, name_span)| {
+                    symbol(name.clone(), kind, span.clone(), name_span.clone(), None)
+                })
+                .collect();
+            groups.push(symbol(
+                format!("{}s", keyword),
+                kind,
+                span,
+                name_span,
+                Some(children),
+            ));
+        }
+        groups
+    }
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(true.into()),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![">".to_string(), " ".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+fn reply<P: DeserializeOwned, R: Serialize>(
+    id: RequestId,
+    params: serde_json::Value,
+    f: impl FnOnce(P) -> R,
+) -> Response {
+    match serde_json::from_value(params) {
+        Ok(params) => Response::new_ok(id, f(params)),
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Uri, Document>,
+}
+
+impl Server {
+    // the document and offset of a position in it
+    fn at(&self, params: &TextDocumentPositionParams) -> Option<(&Document, usize)> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        Some((document, offset(&document.text, params.position)))
+    }
+
+    fn location(&self, uri: &Uri, span: Range<usize>) -> Option<Location> {
+        let document = self.documents.get(uri)?;
+        Some(Location::new(uri.clone(), document.range(span)))
+    }
+
+    // uris hash by their text, whatever clippy sees inside them
+    #[allow(clippy::mutable_key_type)]
+    fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>, String> {
+        let p = params.text_document_position;
+        let Some((document, offset)) = self.at(&p) else {
+            return Ok(None);
+        };
+        let edits = document
+            .rename(offset, &params.new_name)?
+            .into_iter()
+            .map(|span| TextEdit::new(document.range(span), params.new_name.clone()))
+            .collect();
+        let changes = HashMap::from([(p.text_document.uri, edits)]);
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    fn request(&self, request: Request) -> Response {
+        let Request { id, method, params } = request;
+        match method.as_str() {
+            <GotoDefinition as request::Request>::METHOD => {
+                reply(id, params, |p: GotoDefinitionParams| {
+                    let p = p.text_document_position_params;
+                    let (document, offset) = self.at(&p)?;
+                    let location =
+                        self.location(&p.text_document.uri, document.definition(offset)?);
+                    location.map(lsp_types::GotoDefinitionResponse::Scalar)
+                })
+            }
+            <References as request::Request>::METHOD => reply(id, params, |p: ReferenceParams| {
+                let declaration = p.context.include_declaration;
+                let p = p.text_document_position;
+                let (document, offset) = self.at(&p)?;
+                let spans = document.references(offset, declaration);
+                let uri = &p.text_document.uri;
+                spans
+                    .into_iter()
+                    .map(|span| self.location(uri, span))
+                    .collect::<Option<Vec<Location>>>()
+            }),
+            <HoverRequest as request::Request>::METHOD => reply(id, params, |p: HoverParams| {
+                let (document, offset) = self.at(&p.text_document_position_params)?;
+                Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: document.hover(offset)?,
+                    }),
+                    range: None,
+                })
+            }),
+            <Completion as request::Request>::METHOD => reply(id, params, |p: CompletionParams| {
+                let (document, offset) = self.at(&p.text_document_position)?;
+                Some(document.completion(offset))
+            }),
+            <DocumentSymbolRequest as request::Request>::METHOD => {
+                reply(id, params, |p: DocumentSymbolParams| {
+                    let document = self.documents.get(&p.text_document.uri)?;
+                    Some(document.symbols())
+                })
+            }
+            // a refused rename tells the user why
+            <Rename as request::Request>::METHOD => {
+                let edit = serde_json::from_value(params)
+                    .map_err(|e| e.to_string())
+                    .and_then(|p| self.rename(p));
+                match edit {
+                    Ok(edit) => Response::new_ok(id, edit),
+                    Err(message) => Response::new_err(id, ErrorCode::RequestFailed as i32, message),
+                }
+            }
+            _ => Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request {}", method),
+            ),
+        }
+    }
+
+    // keep documents in sync, returning the uri whose diagnostics changed
+    fn notify(&mut self, notification: Notification) -> Option<Uri> {
+        let Notification { method, params } = notification;
+        match method.as_str() {
+            <DidOpenTextDocument as notification::Notification>::METHOD => {
+                let p: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(params).ok()?;
+                let uri = p.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), Document::new(p.text_document.text));
+                Some(uri)
+            }
+            <DidChangeTextDocument as notification::Notification>::METHOD => {
+                let p: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(params).ok()?;
+                let text = p.content_changes.into_iter().last()?.text;
+                let uri = p.text_document.uri;
+                self.documents.insert(uri.clone(), Document::new(text));
+                Some(uri)
+            }
+            <DidCloseTextDocument as notification::Notification>::METHOD => {
+                let p: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(params).ok()?;
+                self.documents.remove(&p.text_document.uri);
+                Some(p.text_document.uri)
+            }
+            _ => None,
+        }
+    }
+
+    fn diagnostics(&self, uri: Uri) -> Notification {
+        let diagnostics = self
+            .documents
+            .get(&uri)
+            .map_or(Vec::new(), |document| document.diagnostics());
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        Notification::new(
+            <PublishDiagnostics as notification::Notification>::METHOD.to_string(),
+            params,
+        )
+    }
+}
+
+fn run(connection: &Connection) -> Result<(), Box<dyn Error>> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                connection
+                    .sender
+                    .send(Message::Response(server.request(request)))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(uri) = server.notify(notification) {
+                    let diagnostics = server.diagnostics(uri);
+                    connection.sender.send(Message::Notification(diagnostics))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+// serve the language server protocol over stdin and stdout until the client
+// shuts it down
+pub fn serve() -> Result<(), Box<dyn Error>> {
+    let (connection, io_threads) = Connection::stdio();
+    run(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use serde_json::json;
+
+    const MODEL: &str = indoc! {"
+        # eml: 0.0.1
+        form AddForm { title: milk, due: today }
+        command Add { use AddForm }
+        context Lists {
+          event Added { use Add }
+          flow { Add => Added }
+        }
+        flow { AddForm => Add => Lists.Added }
+    "};
+
+    // the offset of the nth occurrence of some text
+    fn at(text: &str, n: usize) -> usize {
+        MODEL.match_indices(text).nth(n).unwrap().0
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "# é\n𝄞x\n";
+        let x = text.find('x').unwrap();
+        assert_eq!(position(text, x), Position::new(1, 2));
+        assert_eq!(offset(text, Position::new(1, 2)), x);
+        assert_eq!(offset(text, Position::new(0, 99)), text.find('\n').unwrap());
+        assert_eq!(offset(text, Position::new(9, 0)), text.len());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let document = Document::new(MODEL.replace("Add => Added", "Add => Removed"));
+        let messages = document
+            .diagnostics()
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<(u32, String)>>();
+        assert_eq!(messages, vec![(5, "unknown id Removed".to_string())]);
+        let document = Document::new(MODEL.replace("event Added { use Add }", "event {"));
+        let messages = document
+            .diagnostics()
+            .into_iter()
+            .map(|d| (d.range, d.message))
+            .collect::<Vec<(lsp_types::Range, String)>>();
+        let line = lsp_types::Range::new(Position::new(3, 0), Position::new(3, 15));
+        assert_eq!(messages, vec![(line, "unexpected input".to_string())]);
+        assert!(Document::new(MODEL.to_string()).diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_navigation() {
+        let document = Document::new(MODEL.to_string());
+        let add = at("Add ", 0);
+        assert_eq!(document.definition(at("Add }", 0)), Some(add..add + 3));
+        assert_eq!(document.definition(at("Lists.Added", 0) + 7), {
+            let added = at("Added", 0);
+            Some(added..added + 5)
+        });
+        let references = document
+            .references(add, true)
+            .into_iter()
+            .map(|span| span.start)
+            .collect::<Vec<usize>>();
+        let expected = vec![add, at("Add }", 0), at("Add =>", 0), at("Add =>", 1)];
+        assert_eq!(expected, references);
+        assert_eq!(document.references(add, false).len(), 3);
+    }
+
+    #[test]
+    fn test_hover() {
+        let document = Document::new(MODEL.to_string());
+        let expected = "event Lists.Added\n\n```\ntitle: milk\ndue: today\n```";
+        assert_eq!(document.hover(at("Added", 0)).unwrap(), expected);
+        assert_eq!(document.hover(0), None);
+    }
+
+    #[test]
+    fn test_completion() {
+        let labels = |text: &str, offset: usize| {
+            Document::new(text.to_string())
+                .completion(offset)
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<String>>()
+        };
+        let top = at("Lists.Added", 0) + 2;
+        assert_eq!(labels(MODEL, top), vec!["AddForm", "Add", "Lists.Added"]);
+        let local = at("Added }", 0);
+        assert_eq!(labels(MODEL, local), vec!["AddForm", "Add", "Added"]);
+        let typing = MODEL.replace(
+            "flow { AddForm => Add => Lists.Added }",
+            "flow { AddForm => ",
+        );
+        assert_eq!(labels(&typing, typing.len() - 1).len(), 3);
+        assert!(labels(MODEL, at("AddForm {", 0)).is_empty());
+    }
+
+    #[test]
+    fn test_rename() {
+        let document = Document::new(MODEL.to_string());
+        let spans = document.rename(at("Add =>", 0), "Create").unwrap();
+        let starts = spans.iter().map(|span| span.start).collect::<Vec<usize>>();
+        let expected = vec![
+            at("Add ", 0),
+            at("Add }", 0),
+            at("Add =>", 0),
+            at("Add =>", 1),
+        ];
+        assert_eq!(expected, starts);
+        let refused = document.rename(at("Add =>", 0), "AddForm").unwrap_err();
+        assert_eq!(refused, "AddForm is already defined");
+        assert_eq!(
+            document.rename(0, "X").unwrap_err(),
+            "no card to rename here"
+        );
+    }
+
+    #[test]
+    fn test_symbols() {
+        let document = Document::new(MODEL.to_string());
+        let groups = document
+            .symbols()
+            .into_iter()
+            .map(|group| {
+                let children = group.children.unwrap_or_default();
+                let names = children
+                    .into_iter()
+                    .map(|c| c.name)
+                    .collect::<Vec<String>>();
+                (group.name, names)
+            })
+            .collect::<Vec<(String, Vec<String>)>>();
+        let expected = vec![
+            ("forms", vec!["AddForm"]),
+            ("commands", vec!["Add"]),
+            ("events", vec!["Lists.Added"]),
+            (
+                "flows",
+                vec!["Add => Added", "AddForm => Add => Lists.Added"],
+            ),
+            ("contexts", vec!["Lists"]),
+        ];
+        let expected = expected
+            .into_iter()
+            .map(|(group, names)| {
+                (
+                    group.to_string(),
+                    names.into_iter().map(String::from).collect(),
+                )
+            })
+            .collect::<Vec<(String, Vec<String>)>>();
+        assert_eq!(expected, groups);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let (server, client) = Connection::memory();
+        let thread = std::thread::spawn(move || run(&server).unwrap());
+        let request = |id: i32, method: &str, params: serde_json::Value| {
+            let request = Request::new(id.into(), method.to_string(), params);
+            client.sender.send(Message::Request(request)).unwrap();
+            loop {
+                match client.receiver.recv().unwrap() {
+                    Message::Response(response) => return response.result,
+                    Message::Notification(n) => {
+                        assert_eq!(n.method, "textDocument/publishDiagnostics")
+                    }
+                    Message::Request(_) => unreachable!(),
+                }
+            }
+        };
+        let capabilities = request(1, "initialize", json!({ "capabilities": {} })).unwrap();
+        assert_eq!(capabilities["capabilities"]["definitionProvider"], true);
+        let initialized = Notification::new("initialized".to_string(), json!({}));
+        client
+            .sender
+            .send(Message::Notification(initialized))
+            .unwrap();
+        let uri = "file:///model.eml";
+        let open = json!({ "textDocument": {
+            "uri": uri, "languageId": "eml", "version": 1, "text": MODEL
+        } });
+        let open = Notification::new("textDocument/didOpen".to_string(), open);
+        client.sender.send(Message::Notification(open)).unwrap();
+        let position = position(MODEL, at("Add }", 0));
+        let params = json!({ "textDocument": { "uri": uri }, "position": position });
+        let definition = request(2, "textDocument/definition", params).unwrap();
+        assert_eq!(
+            definition["range"]["start"],
+            json!({ "line": 2, "character": 8 })
+        );
+        let params =
+            json!({ "textDocument": { "uri": uri }, "position": position, "newName": "Create" });
+        let edit = request(3, "textDocument/rename", params).unwrap();
+        assert_eq!(edit["changes"][uri].as_array().unwrap().len(), 4);
+        let unknown = request(5, "textDocument/formatting", json!({}));
+        assert_eq!(unknown, None);
+        request(6, "shutdown", json!(null));
+        let exit = Notification::new("exit".to_string(), json!(null));
+        client.sender.send(Message::Notification(exit)).unwrap();
+        thread.join().unwrap();
+    }
+}
@@ -1,28 +1,109 @@
+mod config;
+mod convert;
+mod cst;
 mod eventmodel;
+mod export;
 mod ingest;
+mod lsp;
 mod parse;
+mod print;
+mod rename;
 mod svg;
+mod symbols;
 mod utils;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use config::{LayoutArgs, ProjectConfig};
+use convert::Language;
+use eventmodel::{emlyml, json, EventModel, ExpressionId};
+use export::Format;
 use parse::parse;
 use std::error::Error;
 use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use svg::*;
-use utils::newid;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Args {
-    /// eml input: either stdin or filepath
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// eml, json or emlyml model input: either stdin or filepath
     #[clap(value_parser, default_value = "-")]
     input: String,
 
     /// eml output: either stdout or filepath
     #[clap(value_parser, default_value = "-")]
     output: String,
+
+    /// theme: default, monochrome, dark, or a toml/json theme file [default: default]
+    #[clap(long, value_parser)]
+    theme: Option<String>,
+
+    /// project config [default: nearest eml.toml]
+    #[clap(long, value_parser)]
+    config: Option<PathBuf>,
+
+    /// diagram title [default: the input file name]
+    #[clap(long, value_parser)]
+    title: Option<String>,
+
+    /// scale the svg to the width of its container
+    #[clap(long, value_parser)]
+    fit: bool,
+
+    /// output format [default: from the output file extension, otherwise svg]
+    #[clap(long, value_enum)]
+    format: Option<Format>,
+
+    /// png pixels per svg pixel
+    #[clap(long, value_parser, default_value_t = 1f32, conflicts_with = "dpi")]
+    scale: f32,
+
+    /// png resolution, 96 is the same as a scale of 1
+    #[clap(long, value_parser)]
+    dpi: Option<f32>,
+
+    /// split pdf output into pages at most this wide, breaking between slices
+    #[clap(long, value_parser)]
+    page_width: Option<f64>,
+
+    /// colour text output by card type with ansi escapes
+    #[clap(long, value_parser)]
+    color: bool,
+
+    #[clap(flatten)]
+    layout: LayoutArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// translate a model between eml and emlyml yaml
+    Convert(ConvertArgs),
+    /// reprint eml files in canonical style
+    Fmt(FmtArgs),
+    /// run a language server for eml files over stdio
+    Lsp,
+    /// rename a card and every reference to it
+    Rename(RenameArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    /// eml, json or emlyml model input: either stdin or filepath
+    #[clap(value_parser, default_value = "-")]
+    input: String,
+
+    /// converted model output: either stdout or filepath
+    #[clap(value_parser, default_value = "-")]
+    output: String,
+
+    /// target language [default: from the output file extension, otherwise emlyml for eml input and eml for the rest]
+    #[clap(long, value_enum)]
+    to: Option<Language>,
 }
 
 fn read_input(input: &str) -> Result<String, Box<dyn Error>> {
@@ -35,40 +116,160 @@ fn read_input(input: &str) -> Result<String, Box<dyn Error>> {
     Ok(buf)
 }
 
-fn write_output(output: &str, target: &str) -> Result<(), Box<dyn Error>> {
+fn write_output(output: &[u8], target: &str) -> Result<(), Box<dyn Error>> {
     let mut writer: Box<dyn io::Write> = match target {
         "-" => Box::new(io::stdout()),
-        _ => Box::new(File::create(&Path::new(target))?),
+        _ => Box::new(File::create(Path::new(target))?),
     };
-    writer.write(output.as_bytes())?;
+    writer.write_all(output)?;
     Ok(())
 }
 
-fn process(input: &str) -> Result<String, Box<dyn Error>> {
-    let mut doc = SvgDocument {
-        id: newid(),
-        width: 1000f64,
-        height: 1000f64,
-        cards: Vec::new(),
-        arrows: Vec::new(),
-        swimlane: Swimlane::new(),
-    };
-    let model = parse(input)?;
-    doc.ingest_expressions(model.expressions);
-    let config = SvgConfig {
-        pad: 150f64,
-        card_width: 300f64,
-        card_height: 150f64,
-    };
-    doc.set_dimensions(&config);
-    let svg_string = doc.render();
-    Ok(svg_string)
+#[derive(clap::Args, Debug)]
+struct FmtArgs {
+    /// eml files to format in place, or - to format stdin to stdout
+    #[clap(value_parser, default_value = "-")]
+    files: Vec<String>,
+
+    /// list the files that are not formatted and fail instead of writing
+    #[clap(long, value_parser)]
+    check: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct RenameArgs {
+    /// the card's id, qualified as Context.Id for a card in a context
+    #[clap(value_parser)]
+    old: String,
+
+    /// the new id, without the context
+    #[clap(value_parser)]
+    new: String,
+
+    /// eml file to rename in, in place, or - to rename stdin to stdout
+    #[clap(value_parser, default_value = "-")]
+    file: String,
+}
+
+fn read_model(input: &str) -> Result<EventModel, String> {
+    if json::is_json(input) {
+        json::from_json(input)
+    } else if emlyml::is_emlyml(input) {
+        emlyml::from_emlyml(input)
+    } else {
+        parse(input)
+    }
+}
+
+fn convert(args: ConvertArgs) -> Result<(), Box<dyn Error>> {
+    let input = read_input(args.input.as_str())?;
+    let to = args
+        .to
+        .or_else(|| Language::from_path(&args.output))
+        .unwrap_or(match json::is_json(&input) || emlyml::is_emlyml(&input) {
+            true => Language::Eml,
+            false => Language::Emlyml,
+        });
+    let output = convert::convert(read_model(&input)?, to)?;
+    write_output(output.as_bytes(), args.output.as_str())
+}
+
+fn fmt(args: FmtArgs) -> Result<(), Box<dyn Error>> {
+    let mut unformatted = Vec::new();
+    for file in args.files.iter() {
+        let input = read_input(file)?;
+        let output = print::format(&input).map_err(|e| format!("{}: {}", file, e))?;
+        if args.check {
+            if output != input {
+                unformatted.push(file.as_str());
+            }
+        } else if file == "-" || output != input {
+            write_output(output.as_bytes(), file)?;
+        }
+    }
+    match unformatted.is_empty() {
+        true => Ok(()),
+        false => Err(format!("not formatted: {}", unformatted.join(", ")).into()),
+    }
+}
+
+fn rename(args: RenameArgs) -> Result<(), Box<dyn Error>> {
+    let input = read_input(&args.file)?;
+    let output = rename::rename(&input, &ExpressionId(args.old), &args.new)
+        .map_err(|e| format!("{}: {}", args.file, e))?;
+    write_output(output.as_bytes(), &args.file)
+}
+
+fn layout(
+    model: EventModel,
+    mut doc: SvgDocument,
+    config: &SvgConfig,
+) -> Result<SvgDocument, String> {
+    doc.ingest_expressions(model.expressions)?;
+    doc.set_dimensions(config)?;
+    Ok(doc)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
+    match args.command {
+        Some(Command::Convert(convert_args)) => return convert(convert_args),
+        Some(Command::Fmt(fmt_args)) => return fmt(fmt_args),
+        Some(Command::Lsp) => return lsp::serve(),
+        Some(Command::Rename(rename_args)) => return rename(rename_args),
+        None => {}
+    }
     let input = read_input(args.input.as_str())?;
-    let output = process(&input)?;
+    let project = ProjectConfig::discover(args.config.as_deref())?;
+    let theme = args.theme.or(project.theme());
+    let theme = Theme::load(theme.as_deref().unwrap_or("default"))?;
+    let config = args.layout.or(project.layout).resolve()?;
+    let file_name = Path::new(&args.input)
+        .file_stem()
+        .filter(|_| args.input != "-")
+        .map(|stem| stem.to_string_lossy().to_string());
+    let title = args
+        .title
+        .or(file_name)
+        .unwrap_or_else(|| "Event model".to_string());
+    let model = read_model(&input)?;
+    let document = |model: EventModel| {
+        let mut doc = SvgDocument::new(theme.clone());
+        doc.fit = args.fit;
+        doc.title = title.clone();
+        layout(model, doc, &config)
+    };
+    let format = args
+        .format
+        .or_else(|| Format::from_path(&args.output))
+        .unwrap_or(Format::Svg);
+    if args.page_width.is_some() && format != Format::Pdf {
+        return Err("--page-width only applies to pdf output".into());
+    }
+    let output = match format {
+        Format::Svg => document(model)?.render().into_bytes(),
+        Format::Png => {
+            let scale = args.dpi.map_or(args.scale, |dpi| dpi / export::CSS_DPI);
+            export::png::render(&document(model)?.render(), scale)?
+        }
+        Format::Pdf => {
+            let doc = document(model)?;
+            match args.page_width {
+                Some(page_width) => export::pdf::render(&doc.render_pages(page_width)?)?,
+                None => export::pdf::render(&[doc.render()])?,
+            }
+        }
+        Format::Html => export::html::render(&document(model)?).into_bytes(),
+        Format::Drawio => export::drawio::render(&document(model)?).into_bytes(),
+        Format::Excalidraw => export::excalidraw::render(&document(model)?).into_bytes(),
+        Format::Text => export::text::render(&document(model)?, args.color).into_bytes(),
+        Format::Json => json::to_json(&model).into_bytes(),
+        Format::Dot => export::dot::render(&model.flatten()?, &theme, &title).into_bytes(),
+        Format::Mermaid => export::mermaid::render(&model.flatten()?, &theme).into_bytes(),
+        Format::Plantuml => {
+            export::plantuml::render(&model.flatten()?, &theme, &title).into_bytes()
+        }
+    };
     write_output(&output, args.output.as_str())?;
     Ok(())
 }
@@ -1,195 +1,127 @@
+use super::cst::{self, Element, Node, SyntaxKind};
 use super::eventmodel::*;
-use super::utils::newid;
-use nom::branch::alt;
-use nom::bytes::complete::{is_not, tag, take_until};
-use nom::character::complete::{alpha1, u32 as parse_u32};
-use nom::character::complete::{line_ending, space0, space1};
-use nom::multi::{many0, many1, separated_list0};
-use nom::sequence::{delimited, preceded, terminated, tuple};
-use nom::IResult;
-
-// parse version info in the first line
-#[derive(Debug, PartialEq)]
-struct Version {
-    major: u32,
-    minor: u32,
-    fix: u32,
-}
-
-struct EmlPrefix();
-
-// parse version info
-fn eml_prefix(input: &str) -> IResult<&str, EmlPrefix> {
-    let hash = tag("#");
-    let eml = tag("eml");
-    let colon = tag(":");
-    let (rest, _) = tuple((hash, space0, eml, colon, space0))(input)?;
-    Ok((rest, EmlPrefix {}))
-}
-
-fn eml_version(input: &str) -> IResult<&str, Version> {
-    let version_info = tuple((parse_u32, tag("."), parse_u32, tag("."), parse_u32));
-    let (rest, (major, _, minor, _, fix)) = preceded(eml_prefix, version_info)(input)?;
-    Ok((rest, Version { major, minor, fix }))
-}
-
-// parse expressions
-fn textfield_key(input: &str) -> IResult<&str, &str> {
-    let key_parser = alpha1;
-    let (newinput, key) = terminated(key_parser, tag(":"))(input)?;
-    Ok((newinput, key))
-}
-
-fn textfield_value(input: &str) -> IResult<&str, &str> {
-    let parse_until_end = is_not(",\n}");
-    let (rest, text) = preceded(space0, parse_until_end)(input)?;
-    Ok((rest, text))
-}
+use super::utils::flow_id;
 
-fn textfield(input: &str) -> IResult<&str, Field> {
-    let (rest, _) = space0(input)?;
-    let (rest, id) = textfield_key(rest)?;
-    let (rest, text) = textfield_value(rest)?;
-    Ok((
-        rest,
-        Field::Text(TextField {
-            name: id.to_string(),
-            data: text.to_string(),
-        }),
-    ))
-}
+// the eventmodel is derived from the lossless syntax tree, which keeps the
+// whitespace and comments that the model has no place for
 
-fn fields(input: &str) -> IResult<&str, Vec<Field>> {
-    let delimiter = alt((tag(","), line_ending));
-    let field_parser = textfield;
-    separated_list0(delimiter, field_parser)(input)
+fn token(node: &Node, kind: SyntaxKind) -> String {
+    node.token(kind).map_or(String::new(), |t| t.text.clone())
 }
 
-fn fields_block(input: &str) -> IResult<&str, Body> {
-    let block_begin = terminated(tag("{"), alt((line_ending, space0)));
-    let block_end = preceded(alt((line_ending, space0)), tag("}"));
-    let (rest, fields) = delimited(block_begin, fields, block_end)(input)?;
-    Ok((rest, Body::FieldBody(fields)))
+fn reference(node: &Node) -> ExpressionId {
+    ExpressionId(node.text())
 }
 
-fn raw_block(input: &str) -> IResult<&str, Body> {
-    let block_begin = terminated(tag("{"), space0);
-    let block_end = preceded(space0, tag("}"));
-    let (rest, raw) = delimited(block_begin, take_until("}"), block_end)(input)?;
-    let rawlines = raw
-        .split("\n")
-        .map(|x| x.trim_start().to_string())
-        .collect::<Vec<String>>();
-    Ok((rest, Body::TableBody(rawlines)))
+fn field(node: &Node) -> Field {
+    Field::Text(TextField {
+        name: token(node, SyntaxKind::Ident),
+        data: token(node, SyntaxKind::FieldValue),
+    })
 }
 
-fn use_block(input: &str) -> IResult<&str, Body> {
-    let block_begin = terminated(tag("{"), space0);
-    let block_end = preceded(space0, tag("}"));
-    let useid = preceded(tag("use "), alpha1);
-    let (rest, exprid) = delimited(block_begin, useid, block_end)(input)?;
-    Ok((rest, Body::UseBody(ExpressionId(exprid.to_string()))))
+fn body(node: &Node) -> Body {
+    match node.kind {
+        SyntaxKind::Use => Body::UseBody(reference(node.node(SyntaxKind::Reference).unwrap())),
+        SyntaxKind::Fields => Body::FieldBody(node.nodes().map(field).collect()),
+        _ => {
+            // everything between the braces, line by line without indents
+            let tokens = node.tokens();
+            let inner = tokens[1..tokens.len() - 1]
+                .iter()
+                .map(|t| t.text.as_str())
+                .collect::<String>();
+            let rawlines = inner
+                .trim_start_matches([' ', '\t'])
+                .split('\n')
+                .map(|x| x.trim_start().to_string())
+                .collect::<Vec<String>>();
+            Body::TableBody(rawlines)
+        }
+    }
 }
 
-fn expression_type(input: &str) -> IResult<&str, ExpressionType> {
-    match alt((
-        tag("form"),
-        tag("job"),
-        tag("command"),
-        tag("event"),
-        tag("view"),
-        tag("flow"),
-    ))(input)
-    {
-        Ok((rest, "form")) => Ok((rest, ExpressionType::Form)),
-        Ok((rest, "job")) => Ok((rest, ExpressionType::Job)),
-        Ok((rest, "command")) => Ok((rest, ExpressionType::Command)),
-        Ok((rest, "event")) => Ok((rest, ExpressionType::Event)),
-        Ok((rest, "view")) => Ok((rest, ExpressionType::View)),
-        Ok((rest, "flow")) => Ok((rest, ExpressionType::Flow)),
-        Ok((_, _)) => unreachable!(), // best option available?
-        Err(e) => Err(e),
+fn expression_type(keyword: &str) -> ExpressionType {
+    match keyword {
+        "form" => ExpressionType::Form,
+        "job" => ExpressionType::Job,
+        "command" => ExpressionType::Command,
+        "event" => ExpressionType::Event,
+        "view" => ExpressionType::View,
+        "flow" => ExpressionType::Flow,
+        _ => ExpressionType::Context,
     }
 }
 
-fn expression_id(input: &str) -> IResult<&str, ExpressionId> {
-    let (rest, (_, id, _)) = tuple((space1, alpha1, space1))(input)?;
-    Ok((rest, ExpressionId(id.to_string())))
+fn expression(node: &Node) -> Expression {
+    let exprid = ExpressionId(token(node, SyntaxKind::Ident));
+    let body = || body(node.nodes().next().unwrap());
+    match expression_type(&token(node, SyntaxKind::Keyword)) {
+        ExpressionType::Form => Expression::Form(exprid, body()),
+        ExpressionType::Job => Expression::Job(exprid, body()),
+        ExpressionType::Command => Expression::Command(exprid, body()),
+        ExpressionType::Event => Expression::Event(exprid, body()),
+        ExpressionType::View => Expression::View(exprid, body()),
+        ExpressionType::Flow => {
+            let ids = node.nodes().map(reference).collect::<Vec<ExpressionId>>();
+            let exprid = ExpressionId(flow_id(ids.iter().map(|id| id.0.as_str())));
+            Expression::Flow(exprid, ids)
+        }
+        ExpressionType::Context => Expression::Context(exprid, expressions(node)),
+    }
 }
 
-fn flow_block(input: &str) -> IResult<&str, Vec<ExpressionId>> {
-    let block_begin = terminated(tag("{"), space0);
-    let block_end = preceded(space0, tag("}"));
-    let expression = preceded(space0, alpha1);
-    let arrow = preceded(space0, tag("=>"));
-    let (rest, ids) = delimited(block_begin, separated_list0(arrow, expression), block_end)(input)?;
-    let expressions = ids
+fn expressions(node: &Node) -> Vec<Expression> {
+    node.children
         .iter()
-        .map(|id| ExpressionId(id.to_string()))
-        .collect::<Vec<ExpressionId>>();
-    Ok((rest, expressions))
+        .filter_map(|child| match child {
+            Element::Node(node) => Some(expression(node)),
+            Element::Token(t) if t.kind == SyntaxKind::Comment => {
+                Some(Expression::Comment(t.text[1..].trim_end().to_string()))
+            }
+            Element::Token(_) => None,
+        })
+        .collect()
 }
 
-fn identified_block(input: &str) -> IResult<&str, (ExpressionId, Body)> {
-    let (rest, exprid) = expression_id(input)?;
-    let (rest, body) = alt((use_block, fields_block, raw_block))(rest)?;
-    Ok((rest, (exprid, body)))
+fn line_of(input: &str, offset: usize) -> usize {
+    input[..offset].matches('\n').count() + 1
 }
 
-fn expression(input: &str) -> IResult<&str, Expression> {
-    let (rest, exprtyp) = expression_type(input)?;
-    match exprtyp {
-        ExpressionType::Form => {
-            let (rest, (exprid, body)) = identified_block(rest)?;
-            Ok((rest, Expression::Form(exprid, body)))
-        }
-        ExpressionType::Job => {
-            let (rest, (exprid, body)) = identified_block(rest)?;
-            Ok((rest, Expression::Job(exprid, body)))
-        }
-        ExpressionType::Command => {
-            let (rest, (exprid, body)) = identified_block(rest)?;
-            Ok((rest, Expression::Command(exprid, body)))
-        }
-        ExpressionType::Event => {
-            let (rest, (exprid, body)) = identified_block(rest)?;
-            Ok((rest, Expression::Event(exprid, body)))
-        }
-        ExpressionType::View => {
-            let (rest, (exprid, body)) = identified_block(rest)?;
-            Ok((rest, Expression::View(exprid, body)))
-        }
-        ExpressionType::Flow => {
-            let (rest, ids) = preceded(space0, flow_block)(rest)?;
-            let exprid = ExpressionId(newid());
-            Ok((rest, Expression::Flow(exprid, ids)))
-        }
+// the model of a parsed file, or why the file is not a model
+pub fn model(file: &Node) -> Result<EventModel, String> {
+    if file.token(SyntaxKind::Header).is_none() {
+        return Err("bad version".to_string());
+    }
+    let error = file.token(SyntaxKind::Error).map(|error| {
+        let offset = error.offset + error.text.len() - error.text.trim_start().len();
+        (offset, "unexpected input")
+    });
+    // the syntax tree has nested contexts, the model does not
+    let nested = file
+        .nodes()
+        .filter(|n| n.kind == SyntaxKind::Context)
+        .find_map(|n| n.node(SyntaxKind::Context))
+        .map(|n| (n.span().start, "contexts cannot be nested"));
+    match error.into_iter().chain(nested).min() {
+        Some((offset, message)) => Err(format!(
+            "{} at line {}",
+            message,
+            line_of(&file.text(), offset)
+        )),
+        None => Ok(partial(file)),
     }
 }
 
-fn comment(input: &str) -> IResult<&str, &str> {
-    delimited(tag("#"), is_not("\n"), line_ending)(input)
-}
-
-fn expressions(input: &str) -> IResult<&str, Vec<Expression>> {
-    let expression_parser = expression;
-    let delimiter = many1(alt((comment, line_ending)));
-    let (rest, expressions) = separated_list0(delimiter, expression_parser)(input)?;
-    Ok((rest, expressions))
+// the model of everything that parsed, ignoring the rest
+pub fn partial(file: &Node) -> EventModel {
+    EventModel {
+        expressions: expressions(file),
+    }
 }
 
 pub fn parse(input: &str) -> Result<EventModel, String> {
-    let (rest, _version) = match eml_version(input) {
-        Ok((rest, version)) => (rest, version),
-        _ => return Err("bad version".to_string()),
-    };
-
-    let mut parser = preceded(many0(alt((line_ending, comment))), expressions);
-
-    match parser(rest) {
-        Ok((_, expressions)) => Ok(EventModel { expressions }),
-        Err(e) => Err(e.to_string()),
-    }
+    model(&cst::parse(input))
 }
 
 #[cfg(test)]
@@ -197,16 +129,59 @@ mod tests {
     use super::*;
     use indoc::indoc;
 
+    fn expressions(input: &str) -> Result<Vec<Expression>, String> {
+        parse(&format!("# eml: 0.0.1\n{}", input)).map(|model| model.expressions)
+    }
+
+    // the body of a card with this block
+    fn block(input: &str) -> Body {
+        match expressions(&format!("form Test {}", input))
+            .unwrap()
+            .remove(0)
+        {
+            Expression::Form(_, body) => body,
+            _ => unreachable!(),
+        }
+    }
+
+    fn fields(input: &str) -> Vec<Field> {
+        match block(&format!("{{{}}}", input)) {
+            Body::FieldBody(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    fn flow_block(input: &str) -> Vec<ExpressionId> {
+        match expressions(&format!("flow {}", input)).unwrap().remove(0) {
+            Expression::Flow(_, ids) => ids,
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn test_eml_version() {
-        let input = "# eml: 0.0.1";
-        let expected = Version {
-            major: 0,
-            minor: 0,
-            fix: 1,
-        };
-        let (_, observed) = eml_version(input).unwrap();
-        assert_eq!(expected, observed)
+        assert!(parse("# eml: 0.0.1").is_ok());
+        assert!(parse(
+            "#eml:1.2.3
+"
+        )
+        .is_ok());
+        assert_eq!(
+            parse(
+                "# eml: 0.1
+"
+            )
+            .unwrap_err(),
+            "bad version"
+        );
+        assert_eq!(
+            parse(
+                "form Foo {}
+"
+            )
+            .unwrap_err(),
+            "bad version"
+        );
     }
 
     #[test]
@@ -216,7 +191,7 @@ mod tests {
             name: "foo".to_string(),
             data: "bar".to_string(),
         });
-        let (_, observed) = textfield(input).unwrap();
+        let observed = fields(input).remove(0);
         assert_eq!(expected, observed)
     }
 
@@ -233,7 +208,7 @@ mod tests {
                 data: "ooka".to_string(),
             }),
         ];
-        let (_, observed) = fields(input).unwrap();
+        let observed = fields(input);
         assert_eq!(expected, observed)
     }
 
@@ -250,7 +225,7 @@ mod tests {
                 data: "ooka".to_string(),
             }),
         ];
-        let (_, observed) = fields(input).unwrap();
+        let observed = fields(input);
         assert_eq!(expected, observed)
     }
 
@@ -267,7 +242,7 @@ mod tests {
                 data: "ooka".to_string(),
             }),
         ]);
-        let (_, observed) = fields_block(input).unwrap();
+        let observed = block(input);
         assert_eq!(expected, observed)
     }
 
@@ -284,7 +259,7 @@ mod tests {
                 data: "ooka".to_string(),
             }),
         ]);
-        let (_, observed) = fields_block(input).unwrap();
+        let observed = block(input);
         assert_eq!(expected, observed)
     }
 
@@ -296,7 +271,7 @@ mod tests {
             ExpressionId("Bar".to_string()),
             ExpressionId("Baz".to_string()),
         ];
-        let (_, observed) = flow_block(input).unwrap();
+        let observed = flow_block(input);
         assert_eq!(expected, observed);
     }
 
@@ -304,7 +279,7 @@ mod tests {
     fn test_use_block_01() {
         let input = "{ use FooBar}";
         let expected = Body::UseBody(ExpressionId("FooBar".to_string()));
-        let (_, observed) = use_block(input).unwrap();
+        let observed = block(input);
         assert_eq!(expected, observed)
     }
 
@@ -312,7 +287,7 @@ mod tests {
     fn test_use_block_02() {
         let input = "{use FooBar}";
         let expected = Body::UseBody(ExpressionId("FooBar".to_string()));
-        let (_, observed) = use_block(input).unwrap();
+        let observed = block(input);
         assert_eq!(expected, observed)
     }
 
@@ -320,7 +295,7 @@ mod tests {
     fn test_use_block_03() {
         let input = "{use FooBar }";
         let expected = Body::UseBody(ExpressionId("FooBar".to_string()));
-        let (_, observed) = use_block(input).unwrap();
+        let observed = block(input);
         assert_eq!(expected, observed)
     }
 
@@ -331,7 +306,7 @@ mod tests {
             ExpressionId("FooForm".to_string()),
             Body::FieldBody(vec![]),
         )];
-        let (_, observed) = expressions(input).unwrap();
+        let observed = expressions(input).unwrap();
         assert_eq!(expected, observed)
     }
 
@@ -345,7 +320,7 @@ mod tests {
                 data: "bar".to_string(),
             })]),
         )];
-        let (_, observed) = expressions(input).unwrap();
+        let observed = expressions(input).unwrap();
         assert_eq!(expected, observed)
     }
 
@@ -376,7 +351,7 @@ mod tests {
                 })]),
             ),
         ];
-        let (_, observed) = expressions(input).unwrap();
+        let observed = expressions(input).unwrap();
         assert_eq!(expected, observed)
     }
 
@@ -401,7 +376,7 @@ mod tests {
                 Body::UseBody(ExpressionId("FooForm".to_string())),
             ),
         ];
-        let (_, observed) = expressions(input).unwrap();
+        let observed = expressions(input).unwrap();
         assert_eq!(expected, observed)
     }
 
@@ -428,7 +403,86 @@ mod tests {
                 "".to_string(),
             ]),
         )];
-        let (_, observed) = expressions(input).unwrap();
+        let observed = expressions(input).unwrap();
+        assert_eq!(expected, observed)
+    }
+
+    #[test]
+    fn test_flow_block_qualified() {
+        let input = "{Billing.AccountAdded => Accounts }";
+        let expected = vec![
+            ExpressionId("Billing.AccountAdded".to_string()),
+            ExpressionId("Accounts".to_string()),
+        ];
+        let observed = flow_block(input);
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_use_block_qualified() {
+        let input = "{ use Billing.AddAccount }";
+        let expected = Body::UseBody(ExpressionId("Billing.AddAccount".to_string()));
+        let observed = block(input);
+        assert_eq!(expected, observed)
+    }
+
+    #[test]
+    fn test_parse_context() {
+        let input = indoc! {r#"
+            context Billing {
+              # Accounts
+              command AddAccount {
+                Name: John
+              }
+              event AccountAdded { use AddAccount }
+            }
+        "#};
+        let expected = vec![Expression::Context(
+            ExpressionId("Billing".to_string()),
+            vec![
+                Expression::Comment(" Accounts".to_string()),
+                Expression::Command(
+                    ExpressionId("AddAccount".to_string()),
+                    Body::FieldBody(vec![Field::Text(TextField {
+                        name: "Name".to_string(),
+                        data: "John".to_string(),
+                    })]),
+                ),
+                Expression::Event(
+                    ExpressionId("AccountAdded".to_string()),
+                    Body::UseBody(ExpressionId("AddAccount".to_string())),
+                ),
+            ],
+        )];
+        let observed = expressions(input).unwrap();
+        assert_eq!(expected, observed)
+    }
+
+    #[test]
+    fn test_parse_context_nested() {
+        let input = "context Billing {\n  context Payments {}\n}";
+        let observed = expressions(input).unwrap_err();
+        assert_eq!(observed, "contexts cannot be nested at line 3");
+        let input = "context Billing {\n  context Payments {}\n}\nform A { ??? ";
+        let observed = expressions(input).unwrap_err();
+        assert_eq!(observed, "contexts cannot be nested at line 3");
+        let input = "form A ???\ncontext Billing {\n  context Payments {}\n}";
+        let observed = expressions(input).unwrap_err();
+        assert_eq!(observed, "unexpected input at line 2")
+    }
+
+    #[test]
+    fn test_parse_flow_id() {
+        let input = "flow { CustomerForm => AddCustomer => CustomerAdded }";
+        let expected = vec![Expression::Flow(
+            ExpressionId("flow-CustomerForm-AddCustomer-CustomerAdded".to_string()),
+            vec![
+                ExpressionId("CustomerForm".to_string()),
+                ExpressionId("AddCustomer".to_string()),
+                ExpressionId("CustomerAdded".to_string()),
+            ],
+        )];
+        let observed = expressions(input).unwrap();
         assert_eq!(expected, observed)
     }
 }
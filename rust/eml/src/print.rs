@@ -0,0 +1,381 @@
+use super::cst::{self, SyntaxKind};
+use super::eventmodel::*;
+use super::parse::model;
+
+// the version written for models that come without a header
+const VERSION: &str = "0.0.1";
+const INDENT: &str = "  ";
+
+// the grammar only accepts ascii letters in ids and field names
+pub fn is_word(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+pub fn check_id(id: &ExpressionId) -> Result<&str, String> {
+    match is_word(&id.0) {
+        true => Ok(&id.0),
+        false => Err(format!("{} is not a valid eml id", id.0)),
+    }
+}
+
+pub fn check_reference(id: &ExpressionId) -> Result<&str, String> {
+    match id.0.split('.').all(is_word) {
+        true => Ok(&id.0),
+        false => Err(format!("{} is not a valid eml reference", id.0)),
+    }
+}
+
+fn block(lines: &[String], indent: &str) -> String {
+    let inner = lines
+        .iter()
+        .map(|line| format!("{}{}{}\n", indent, INDENT, line))
+        .collect::<String>();
+    format!("{{\n{}{}}}", inner, indent)
+}
+
+fn is_rule(row: &[&str]) -> bool {
+    row.iter()
+        .all(|cell| cell.contains('-') && cell.chars().all(|c| c == '-' || c == ':'))
+}
+
+fn pad(cell: &str, width: usize, rule: Option<&str>) -> String {
+    let fill = width - cell.chars().count();
+    match rule.map(|r| (r.starts_with(':'), r.ends_with(':'))) {
+        Some((false, true)) => format!("{}{}", " ".repeat(fill), cell),
+        Some((true, true)) => {
+            let left = fill / 2;
+            format!("{}{}{}", " ".repeat(left), cell, " ".repeat(fill - left))
+        }
+        _ => format!("{}{}", cell, " ".repeat(fill)),
+    }
+}
+
+// pipe tables get their columns padded to a common width, aligned as the
+// colons of a |:---|---:| rule line ask
+fn align(lines: &[String]) -> Option<Vec<String>> {
+    let rows = lines
+        .iter()
+        .map(|line| {
+            let inner = line.strip_prefix('|')?.strip_suffix('|')?;
+            Some(inner.split('|').map(|c| c.trim()).collect::<Vec<&str>>())
+        })
+        .collect::<Option<Vec<Vec<&str>>>>()?;
+    let mut widths = vec![1; rows.iter().map(|r| r.len()).max()?];
+    for row in rows.iter().filter(|row| !is_rule(row)) {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let rule = rows.iter().find(|row| is_rule(row));
+    let aligned = rows
+        .iter()
+        .map(|row| {
+            let cells = row.iter().zip(widths.iter());
+            if is_rule(row) {
+                let cells = cells
+                    .map(|(cell, width)| {
+                        let left = if cell.starts_with(':') { ":" } else { "-" };
+                        let right = if cell.ends_with(':') { ":" } else { "-" };
+                        format!("{}{}{}", left, "-".repeat(*width), right)
+                    })
+                    .collect::<Vec<String>>();
+                return format!("|{}|", cells.join("|"));
+            }
+            let cells = cells
+                .enumerate()
+                .map(|(i, (cell, width))| pad(cell, *width, rule.and_then(|r| r.get(i).copied())))
+                .collect::<Vec<String>>();
+            format!("| {} |", cells.join(" | "))
+        })
+        .collect();
+    Some(aligned)
+}
+
+fn body(body: &Body, indent: &str) -> Result<String, String> {
+    let lines = match body {
+        Body::UseBody(id) => return Ok(format!("{{ use {} }}", check_reference(id)?)),
+        Body::FieldBody(fields) => {
+            let fields = fields
+                .iter()
+                .map(|f| match f {
+                    Field::Text(ff) => (ff.name.as_str(), ff.data.trim()),
+                })
+                .collect::<Vec<(&str, &str)>>();
+            let printable = fields
+                .iter()
+                .all(|(name, data)| is_word(name) && !data.contains([',', '}', '\n']));
+            let lines = fields
+                .iter()
+                .map(|(name, data)| format!("{}: {}", name, data))
+                .collect::<Vec<String>>();
+            if printable {
+                return Ok(match lines.is_empty() {
+                    true => "{}".to_string(),
+                    false => block(&lines, indent),
+                });
+            }
+            // a field the grammar cannot hold keeps its text as a raw line
+            lines
+        }
+        Body::TableBody(lines) => lines
+            .iter()
+            .map(|line| line.trim().to_string())
+            .collect::<Vec<String>>(),
+    };
+    if let Some(line) = lines.iter().find(|line| line.contains('}')) {
+        return Err(format!("{} cannot be written in an eml block", line));
+    }
+    let start = lines
+        .iter()
+        .position(|l| !l.is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|l| !l.is_empty())
+        .map_or(start, |i| i + 1);
+    let lines = &lines[start..end];
+    Ok(match align(lines) {
+        _ if lines.is_empty() => "{}".to_string(),
+        Some(table) => block(&table, indent),
+        None => block(lines, indent),
+    })
+}
+
+fn expression(expression: &Expression, indent: &str) -> Result<String, String> {
+    let (keyword, id, card) = match expression {
+        Expression::Form(id, b) => ("form", id, b),
+        Expression::Job(id, b) => ("job", id, b),
+        Expression::Command(id, b) => ("command", id, b),
+        Expression::Event(id, b) => ("event", id, b),
+        Expression::View(id, b) => ("view", id, b),
+        Expression::Flow(_, ids) => {
+            let ids = ids
+                .iter()
+                .map(check_reference)
+                .collect::<Result<Vec<&str>, String>>()?;
+            return Ok(format!("{}flow {{ {} }}", indent, ids.join(" => ")));
+        }
+        Expression::Comment(text) => return Ok(format!("{}#{}", indent, text)),
+        Expression::Context(id, exprs) => {
+            let inner = expressions(exprs, &format!("{}{}", indent, INDENT))?;
+            return Ok(format!(
+                "{}context {} {{\n{}{}}}",
+                indent,
+                check_id(id)?,
+                inner,
+                indent
+            ));
+        }
+    };
+    Ok(format!(
+        "{}{} {} {}",
+        indent,
+        keyword,
+        check_id(id)?,
+        body(card, indent)?
+    ))
+}
+
+// one expression per line, with multi-line blocks and comments set apart by
+// blank lines. comments stay attached to the expression below them
+fn expressions(exprs: &[Expression], indent: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut previous: Option<(bool, bool)> = None;
+    for expr in exprs {
+        let text = expression(expr, indent)?;
+        let comment = matches!(expr, Expression::Comment(_));
+        let multiline = text.contains('\n');
+        if let Some((after_comment, after_multiline)) = previous {
+            if !after_comment && (comment || multiline || after_multiline) {
+                out.push('\n');
+            }
+        }
+        out.push_str(&text);
+        out.push('\n');
+        previous = Some((comment, multiline));
+    }
+    Ok(out)
+}
+
+fn print_version(model: &EventModel, version: &str) -> Result<String, String> {
+    Ok(format!(
+        "# eml: {}\n\n{}",
+        version,
+        expressions(&model.expressions, "")?
+    ))
+}
+
+// the model as eml text in canonical style
+pub fn print(model: &EventModel) -> Result<String, String> {
+    print_version(model, VERSION)
+}
+
+// eml text reprinted in canonical style, keeping the version it declares
+pub fn format(input: &str) -> Result<String, String> {
+    let file = cst::parse(input);
+    let model = model(&file)?;
+    let version = file
+        .token(SyntaxKind::Header)
+        .and_then(|header| header.text.split_once("eml:"))
+        .map_or(VERSION, |(_, version)| version.trim());
+    print_version(&model, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_print() {
+        let input = indoc! {"
+            # eml: 0.0.1
+            form AddTodoForm { title: milk, due: today }
+            command AddTodo { use AddTodoForm }
+            event TodoAdded { use AddTodo }
+            job Reminder {}
+            context Todos {
+                view Todos {
+                  | title | due   |
+                  | milk  | today |
+                }
+              flow { TodoAdded => Todos }
+            }
+            flow { AddTodoForm => AddTodo => TodoAdded }
+        "};
+        let expected = indoc! {"
+            # eml: 0.0.1
+
+            form AddTodoForm {
+              title: milk
+              due: today
+            }
+
+            command AddTodo { use AddTodoForm }
+            event TodoAdded { use AddTodo }
+            job Reminder {}
+
+            context Todos {
+              view Todos {
+                | title | due   |
+                | milk  | today |
+              }
+
+              flow { TodoAdded => Todos }
+            }
+
+            flow { AddTodoForm => AddTodo => TodoAdded }
+        "};
+        let observed = print(&parse(input).unwrap()).unwrap();
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_format_comments_and_tables() {
+        let input = indoc! {"
+            # eml: 0.0.1
+            # Customer Entry
+            # --------------
+            form CustomerForm { Name:John, Age: 21}
+            view Customers {
+              |  Id | Name |  Age |
+              |----|:----:|---:|
+              | 123 | John | 21 |
+            }
+            context Billing {
+            # accounts
+            command AddAccount {}
+            }
+            # the end
+        "};
+        let expected = indoc! {"
+            # eml: 0.0.1
+
+            # Customer Entry
+            # --------------
+            form CustomerForm {
+              Name: John
+              Age: 21
+            }
+
+            view Customers {
+              | Id  | Name | Age |
+              |-----|:----:|----:|
+              | 123 | John |  21 |
+            }
+
+            context Billing {
+              # accounts
+              command AddAccount {}
+            }
+
+            # the end
+        "};
+        let observed = format(input).unwrap();
+        assert_eq!(expected, observed);
+        assert_eq!(format(&observed).unwrap(), observed);
+    }
+
+    #[test]
+    fn test_format_keeps_version() {
+        let observed = format("#eml:1.2.3\nform A { a: b }\n").unwrap();
+        assert_eq!(observed, "# eml: 1.2.3\n\nform A {\n  a: b\n}\n");
+    }
+
+    #[test]
+    fn test_format_rejects_trailing_input() {
+        let input = "# eml: 0.0.1\ncommand AddTodo {}\nevent TodoAdded { oops\n";
+        assert_eq!(format(input).unwrap_err(), "unexpected input at line 3");
+    }
+
+    #[test]
+    fn test_print_parses() {
+        let printed = print(
+            &parse(indoc! {"
+            # eml: 0.0.1
+            command AddTodo { title: milk }
+            view Todos {
+              | title |
+            }
+        "})
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(print(&parse(&printed).unwrap()).unwrap(), printed);
+    }
+
+    #[test]
+    fn test_print_raw_fields() {
+        let model = EventModel {
+            expressions: vec![Expression::Form(
+                ExpressionId("Contact".to_string()),
+                Body::FieldBody(vec![Field::Text(TextField {
+                    name: "Email".to_string(),
+                    data: "a@example.com, b@example.com".to_string(),
+                })]),
+            )],
+        };
+        let expected = "# eml: 0.0.1\n\nform Contact {\n  Email: a@example.com, b@example.com\n}\n";
+        assert_eq!(print(&model).unwrap(), expected);
+        let printed = parse(expected).unwrap();
+        assert!(matches!(
+            &printed.expressions[0],
+            Expression::Form(_, Body::TableBody(_))
+        ));
+    }
+
+    #[test]
+    fn test_print_invalid_id() {
+        let model = EventModel {
+            expressions: vec![Expression::Job(
+                ExpressionId("send_mail".to_string()),
+                Body::FieldBody(vec![]),
+            )],
+        };
+        assert_eq!(
+            print(&model).unwrap_err(),
+            "send_mail is not a valid eml id"
+        );
+    }
+}
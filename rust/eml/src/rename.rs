@@ -0,0 +1,147 @@
+use super::cst::{self, Node};
+use super::eventmodel::ExpressionId;
+use super::parse::model;
+use super::print::is_word;
+use super::symbols::Symbols;
+use std::ops::Range;
+
+fn apply(file: &mut Node, spans: &[Range<usize>], new: &str) {
+    for token in file.tokens_mut() {
+        if spans.contains(&token.span()) {
+            token.text = new.to_string();
+        }
+    }
+}
+
+// the spans of a card's id and of every reference to it, which a rename
+// replaces with the new name. qualified references keep their context
+pub fn spans(input: &str, old: &ExpressionId, new: &str) -> Result<Vec<Range<usize>>, String> {
+    if !is_word(new) {
+        return Err(format!("{} is not a valid eml id", new));
+    }
+    let mut file = cst::parse(input);
+    model(&file)?;
+    let symbols = Symbols::new(&file);
+    let definition = symbols
+        .definition(old)
+        .ok_or(format!("unknown id {}", old.0))?;
+    let renamed = match &definition.context {
+        Some(ctx) => ExpressionId(new.to_string()).qualified(ctx),
+        None => ExpressionId(new.to_string()),
+    };
+    if &renamed != old && symbols.definition(&renamed).is_some() {
+        return Err(format!("{} is already defined", renamed.0));
+    }
+    let definitions = symbols
+        .cards()
+        .filter(|d| &d.id == old)
+        .map(|d| d.name.clone());
+    let references = symbols.references_to(old).map(|r| r.name.clone());
+    let mut spans = definitions.chain(references).collect::<Vec<Range<usize>>>();
+    spans.sort_by_key(|span| span.start);
+    // the new name must not capture references to other cards, such as a
+    // bare id in a context that would now find the context's own card
+    apply(&mut file, &spans, new);
+    let after = Symbols::new(&cst::parse(&file.text()));
+    let expected = symbols.references.iter().map(|r| match &r.id == old {
+        true => &renamed,
+        false => &r.id,
+    });
+    if !expected.eq(after.references.iter().map(|r| &r.id)) {
+        return Err(format!(
+            "renaming {} to {} would change what other references point at",
+            old.0, new
+        ));
+    }
+    Ok(spans)
+}
+
+// the input with a card and its references renamed, and nothing else changed
+pub fn rename(input: &str, old: &ExpressionId, new: &str) -> Result<String, String> {
+    let spans = spans(input, old, new)?;
+    let mut file = cst::parse(input);
+    apply(&mut file, &spans, new);
+    Ok(file.text())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const MODEL: &str = indoc! {"
+        # eml: 0.0.1
+
+        # customers
+        form CustomerForm { Name: John }
+        command AddCustomer { use CustomerForm }
+        event CustomerAdded {use AddCustomer}
+        context Billing {
+          command AddAccount { use AddCustomer }
+          event AccountAdded { use AddAccount }
+          flow { AddCustomer => AddAccount=>AccountAdded }
+        }
+        flow { CustomerForm => AddCustomer => Billing.AddAccount }
+    "};
+
+    fn id(id: &str) -> ExpressionId {
+        ExpressionId(id.to_string())
+    }
+
+    #[test]
+    fn test_rename() {
+        let expected = indoc! {"
+            # eml: 0.0.1
+
+            # customers
+            form CustomerForm { Name: John }
+            command CreateCustomer { use CustomerForm }
+            event CustomerAdded {use CreateCustomer}
+            context Billing {
+              command AddAccount { use CreateCustomer }
+              event AccountAdded { use AddAccount }
+              flow { CreateCustomer => AddAccount=>AccountAdded }
+            }
+            flow { CustomerForm => CreateCustomer => Billing.AddAccount }
+        "};
+        let observed = rename(MODEL, &id("AddCustomer"), "CreateCustomer").unwrap();
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_rename_in_context() {
+        let observed = rename(MODEL, &id("Billing.AddAccount"), "OpenAccount").unwrap();
+        assert!(observed.contains("  command OpenAccount { use AddCustomer }"));
+        assert!(observed.contains("  event AccountAdded { use OpenAccount }"));
+        assert!(observed.contains("  flow { AddCustomer => OpenAccount=>AccountAdded }"));
+        assert!(observed.contains("AddCustomer => Billing.OpenAccount }"));
+    }
+
+    #[test]
+    fn test_rename_refused() {
+        let refused = |old: &str, new: &str| rename(MODEL, &id(old), new).unwrap_err();
+        assert_eq!(
+            refused("AddCustomer", "CustomerForm"),
+            "CustomerForm is already defined"
+        );
+        assert_eq!(
+            refused("Billing.AddAccount", "AccountAdded"),
+            "Billing.AccountAdded is already defined"
+        );
+        assert_eq!(
+            refused("AddAccount", "OpenAccount"),
+            "unknown id AddAccount"
+        );
+        assert_eq!(
+            refused("AddCustomer", "add_customer"),
+            "add_customer is not a valid eml id"
+        );
+        // AddCustomer inside Billing would find Billing.AddCustomer instead
+        assert_eq!(
+            refused("Billing.AccountAdded", "AddCustomer"),
+            "renaming Billing.AccountAdded to AddCustomer would change what other references point at"
+        );
+        let broken = rename("# eml: 0.0.1\nform A {", &id("A"), "B").unwrap_err();
+        assert_eq!(broken, "unexpected input at line 2");
+    }
+}
@@ -1,42 +1,29 @@
+use super::route::{Port, Router, Side};
+use super::theme::Theme;
+use super::xml::escape;
 use super::{Card, CardType, Point};
-use crate::utils::newid;
-
-fn det(a: (f64, f64), b: (f64, f64)) -> f64 {
-    a.0 * b.1 - a.1 * b.0
-}
-
-fn line_intersection(
-    line1: ((f64, f64), (f64, f64)),
-    line2: ((f64, f64), (f64, f64)),
-) -> (f64, f64) {
-    let xdiff = (line1.0 .0 - line1.1 .0, line2.0 .0 - line2.1 .0);
-    let ydiff = (line1.0 .1 - line1.1 .1, line2.0 .1 - line2.1 .1);
-    let div = det(xdiff, ydiff);
-    if div == 0f64 {
-        panic!("Lines do not intersect");
-    }
-    let d = (det(line1.0, line1.1), det(line2.0, line2.1));
-    let x = det(d, xdiff) / div;
-    let y = det(d, ydiff) / div;
-    (x, y)
-}
+use crate::utils::flow_id;
 
 pub struct Arrow {
     pub begin_at: Card,
     pub end_at: Card,
     pub id: String,
+    pub xml_id: String,
+    pub points: Vec<Point>,
 }
 
 impl Arrow {
     pub fn new(begin_at: Card, end_at: Card) -> Self {
         Arrow {
+            id: flow_id([begin_at.id.as_str(), end_at.id.as_str()]),
             begin_at,
             end_at,
-            id: newid(),
+            xml_id: String::new(),
+            points: Vec::new(),
         }
     }
 
-    pub fn set_dimensions(&mut self, cards: &Vec<Card>) {
+    pub fn set_dimensions(&mut self, cards: &[Card]) {
         self.begin_at = cards
             .iter()
             .find(|c| c.id == self.begin_at.id)
@@ -49,89 +36,60 @@ impl Arrow {
             .clone();
     }
 
-    pub fn get_points(&self) -> (Point, Point) {
-        let arrowhead = 15f64;
-
-        match (&self.begin_at.card_type, &self.end_at.card_type) {
-            (CardType::Job, CardType::Command) => (
-                self.begin_at.right_anchor(),
-                Point {
-                    x: self.end_at.top_anchor().x,
-                    y: self.end_at.top_anchor().y - arrowhead,
-                },
-            ),
-            (CardType::Form, CardType::Command) => (
-                self.begin_at.right_anchor(),
-                Point {
-                    x: self.end_at.top_anchor().x,
-                    y: self.end_at.top_anchor().y - arrowhead,
-                },
-            ),
-            (CardType::Command, CardType::Event) => (
-                self.begin_at.bottom_anchor(),
-                Point {
-                    x: self.end_at.left_anchor().x - arrowhead,
-                    y: self.end_at.left_anchor().y,
-                },
-            ),
-            (CardType::Event, CardType::View) => (
-                self.begin_at.right_anchor(),
-                Point {
-                    x: self.end_at.bottom_anchor().x,
-                    y: self.end_at.bottom_anchor().y + arrowhead,
-                },
-            ),
-            (CardType::View, CardType::Job) => (
-                self.begin_at.top_anchor(),
-                Point {
-                    x: self.end_at.left_anchor().x - arrowhead,
-                    y: self.end_at.left_anchor().y,
-                },
-            ),
-            (CardType::View, CardType::Form) => (
-                self.begin_at.top_anchor(),
-                Point {
-                    x: self.end_at.left_anchor().x - arrowhead,
-                    y: self.end_at.left_anchor().y,
-                },
-            ),
-            (_, _) => panic!("Illegal Arrow!"),
-        }
+    // the cards share a column, one above the other
+    pub fn is_stacked(&self) -> bool {
+        let (begin, end) = (&self.begin_at, &self.end_at);
+        begin.origin.x < end.origin.x + end.width && end.origin.x < begin.origin.x + begin.width
     }
 
-    pub fn render(&self) -> String {
-        let (left, right) = self.get_points();
-        let control_point = match &self.begin_at.card_type {
-            CardType::Job | CardType::Form => {
-                let vertical = ((right.x, right.y), (right.x, 99999f64));
-                let horizontal = ((left.x, left.y), (0f64, left.y));
-                line_intersection(vertical, horizontal)
+    // the sides of the begin and end cards the arrow connects, if cards of
+    // these types can be connected at all
+    pub fn get_sides(&self) -> Result<(Side, Side), String> {
+        let sides = match (&self.begin_at.card_type, &self.end_at.card_type) {
+            (CardType::Form | CardType::Job, CardType::Command) if self.is_stacked() => {
+                (Side::Bottom, Side::Top)
             }
-            CardType::Command => {
-                let vertical = ((left.x, left.y), (left.x, 99999f64));
-                let horizontal = ((right.x, right.y), (0f64, right.y));
-                line_intersection(vertical, horizontal)
-            }
-            CardType::Event => {
-                let vertical = ((right.x, right.y), (right.x, 99999f64));
-                let horizontal = ((left.x, left.y), (0f64, left.y));
-                line_intersection(vertical, horizontal)
-            }
-            CardType::View => {
-                let vertical = ((left.x, left.y), (left.x, 99999f64));
-                let horizontal = ((right.x, right.y), (0f64, right.y));
-                line_intersection(vertical, horizontal)
+            (CardType::Command, CardType::Event) if self.is_stacked() => (Side::Bottom, Side::Top),
+            (CardType::Job, CardType::Command) => (Side::Right, Side::Top),
+            (CardType::Form, CardType::Command) => (Side::Right, Side::Top),
+            (CardType::Command, CardType::Event) => (Side::Bottom, Side::Left),
+            (CardType::Event, CardType::View) => (Side::Right, Side::Bottom),
+            (CardType::View, CardType::Job) => (Side::Top, Side::Left),
+            (CardType::View, CardType::Form) => (Side::Top, Side::Left),
+            (from, to) => {
+                return Err(format!(
+                    "a flow cannot go from {} {} to {} {}",
+                    from.name(),
+                    self.begin_at.id,
+                    to.name(),
+                    self.end_at.id
+                ))
             }
         };
-        format!("\
-        <path d='M {left_x} {left_y} Q {cp0} {cp1} {right_x} {right_y}' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' />\
+        Ok(sides)
+    }
+
+    pub fn route(&mut self, router: &mut Router, start: Port, end: Port) {
+        self.points = router.route(&start, &end);
+    }
+
+    pub fn render(&self, theme: &Theme) -> String {
+        let path = self
+            .points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| format!("{} {} {}", if i == 0 { "M" } else { "L" }, p.x, p.y))
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!(
+            "\
+        <path id='{id}' d='{path}' {stroke} fill='none' marker-end='url(#triangle)' role='img' aria-label='flow from {from} to {to}' />\
         ",
-        left_x=left.x,
-        left_y=left.y,
-        cp0=control_point.0,
-        cp1=control_point.1,
-        right_x=right.x,
-        right_y=right.y,
+            id = self.xml_id,
+            path = path,
+            from = escape(&self.begin_at.id),
+            to = escape(&self.end_at.id),
+            stroke = theme.arrow.attributes(),
         )
     }
 }
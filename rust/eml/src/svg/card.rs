@@ -1,3 +1,10 @@
+use super::route::Side;
+use super::theme::{CardStyle, Theme};
+use super::xml::escape;
+
+const SEPARATOR: &str = "==========";
+const ELLIPSIS: char = '\u{2026}';
+
 #[derive(Clone, Debug)]
 pub struct Point {
     pub x: f64,
@@ -10,7 +17,7 @@ pub struct TextTranslate {
     pub y: f64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum CardType {
     Job,
     Form,
@@ -19,14 +26,77 @@ pub enum CardType {
     View,
 }
 
+impl CardType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CardType::Job => "job",
+            CardType::Form => "form",
+            CardType::Command => "command",
+            CardType::Event => "event",
+            CardType::View => "view",
+        }
+    }
+}
+
+// how lines longer than the card are shown
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextOverflow {
+    Wrap,
+    Truncate,
+}
+
+fn truncate(line: &str, max_chars: usize) -> String {
+    if line.chars().count() <= max_chars {
+        return line.to_string();
+    }
+    let mut truncated = line
+        .chars()
+        .take(max_chars.saturating_sub(1))
+        .collect::<String>();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+// break on the last space that fits, continuation lines are indented
+fn wrap(line: &str, max_chars: usize) -> Vec<String> {
+    let indent = "  ";
+    let mut lines = Vec::new();
+    let mut rest = line.chars().collect::<Vec<char>>();
+    let mut width = max_chars.max(1);
+    let mut prefix = 0;
+    while rest.len() > width {
+        let split = rest[..=width]
+            .iter()
+            .rposition(|c| *c == ' ')
+            .filter(|pos| *pos > prefix)
+            .unwrap_or(width);
+        let head = rest[..split].iter().collect::<String>();
+        lines.push(head.trim_end().to_string());
+        let tail = rest[split..].iter().collect::<String>();
+        rest = format!("{}{}", indent, tail.trim_start())
+            .chars()
+            .collect::<Vec<char>>();
+        width = max_chars.max(indent.len() + 1);
+        prefix = indent.len();
+    }
+    lines.push(rest.iter().collect::<String>().trim_end().to_string());
+    lines
+}
+
 #[derive(Clone, Debug)]
 pub struct Card {
     pub id: String,
+    // the id of the card's element, given out by the document
+    pub xml_id: String,
     pub card_type: CardType,
     pub origin: Point,
     pub width: f64,
     pub height: f64,
     pub text_lines: Vec<String>,
+    pub text_overflow: TextOverflow,
+    pub display_lines: Vec<String>,
+    pub max_chars: usize,
+    pub max_title_chars: usize,
     pub text_translate: TextTranslate,
 }
 
@@ -34,8 +104,13 @@ impl Card {
     pub fn new(id: String, card_type: CardType, text_lines: Vec<String>) -> Self {
         Card {
             id,
+            xml_id: String::new(),
             card_type,
+            display_lines: text_lines.clone(),
             text_lines,
+            text_overflow: TextOverflow::Wrap,
+            max_chars: usize::MAX,
+            max_title_chars: usize::MAX,
             origin: Point { x: 0f64, y: 0f64 },
             width: 0f64,
             height: 0f64,
@@ -49,76 +124,203 @@ impl Card {
         Point { x, y }
     }
 
-    pub fn right_anchor(&self) -> Point {
-        let x = self.origin.x + self.width;
-        let y = self.origin.y + self.height / 2f64;
-        Point { x, y }
-    }
-
-    pub fn bottom_anchor(&self) -> Point {
-        let x = self.origin.x + self.width / 2f64;
-        let y = self.origin.y + self.height;
-        Point { x, y }
-    }
-
     pub fn left_anchor(&self) -> Point {
         let x = self.origin.x;
         let y = self.origin.y + self.height / 2f64;
         Point { x, y }
     }
 
+    // a point on the given side, fraction of the way along it
+    pub fn side_anchor(&self, side: &Side, fraction: f64) -> Point {
+        let (x, y, w, h) = (self.origin.x, self.origin.y, self.width, self.height);
+        match side {
+            Side::Top => Point {
+                x: x + w * fraction,
+                y,
+            },
+            Side::Right => Point {
+                x: x + w,
+                y: y + h * fraction,
+            },
+            Side::Bottom => Point {
+                x: x + w * fraction,
+                y: y + h,
+            },
+            Side::Left => Point {
+                x,
+                y: y + h * fraction,
+            },
+        }
+    }
+
     pub fn set_origin(&mut self, x: f64, y: f64) {
         self.origin = Point { x, y };
     }
 
-    pub fn set_dimensions(&mut self, width: f64, height: f64, pad: f64) {
+    // wrap or truncate the text lines so none is wider than max_width
+    pub fn fit_text(&mut self, max_width: f64, inset: f64, theme: &Theme) {
+        let max_chars = ((max_width - inset * 2f64) / theme.char_width())
+            .floor()
+            .max(1f64) as usize;
+        self.display_lines = match self.text_overflow {
+            TextOverflow::Wrap => self
+                .text_lines
+                .iter()
+                .flat_map(|line| wrap(line, max_chars))
+                .collect::<Vec<String>>(),
+            TextOverflow::Truncate => self
+                .text_lines
+                .iter()
+                .map(|line| truncate(line, max_chars))
+                .collect::<Vec<String>>(),
+        };
+        self.max_chars = max_chars;
+        self.max_title_chars = (max_chars as f64 / theme.title_scale()).floor() as usize;
+    }
+
+    pub fn title(&self) -> String {
+        truncate(&self.id, self.max_title_chars)
+    }
+
+    // the width and height needed to show the title and display lines
+    pub fn text_size(&self, inset: f64, theme: &Theme) -> (f64, f64) {
+        let title_width =
+            self.title().chars().count() as f64 * theme.char_width() * theme.title_scale();
+        let lines_width = self
+            .display_lines
+            .iter()
+            .chain(std::iter::once(&SEPARATOR.to_string()))
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as f64
+            * theme.char_width();
+        let width = title_width.max(lines_width) + inset * 2f64;
+        let height = (self.display_lines.len() + 2) as f64 * theme.line_height() + inset * 2f64;
+        (width, height)
+    }
+
+    pub fn set_dimensions(&mut self, width: f64, height: f64, inset: f64) {
         self.height = height;
         self.width = width;
-        self.text_translate.x = self.origin.x as f64 + pad as f64 / 12f64;
-        self.text_translate.y = self.origin.y as f64 + pad as f64 / 12f64;
+        self.text_translate.x = self.origin.x + inset;
+        self.text_translate.y = self.origin.y + inset;
     }
 
-    fn _render(&self, fill: &str) -> String {
+    fn _render(&self, style: &CardStyle, theme: &Theme) -> String {
         let lines = self
-            .text_lines
+            .display_lines
             .iter()
-            .map(|line| format!("<tspan x='0' dy='1rem'>{}</tspan>", line))
+            .map(|line| {
+                format!(
+                    "<tspan x='0' dy='{}'>{}</tspan>",
+                    theme.line_height(),
+                    escape(line)
+                )
+            })
             .collect::<Vec<String>>()
             .join("");
         let text = format!(
                 "\
-            <text transform='translate({translate_x} {translate_y})' x='0' y='0' style='white-space: pre;' font-family='monospace' >\
-            <tspan x='0' dy='1rem' font-size='larger' font-weight='bold'>{id}</tspan>\
-            <tspan x='0' dy='1rem'>==========</tspan>\
+            <text transform='translate({translate_x} {translate_y})' x='0' y='0' style='white-space: pre;' font-family='{font_family}' font-size='{font_size}' fill='{color}' >\
+            <tspan x='0' dy='{line_height}' font-size='{title_font_size}' font-weight='bold'>{title}</tspan>\
+            <tspan x='0' dy='{line_height}'>{separator}</tspan>\
             {lines}\
             </text>\
             ",
-                id = self.id,
+                title = escape(&self.title()),
+                separator = SEPARATOR,
                 translate_x = self.text_translate.x,
                 translate_y = self.text_translate.y,
+                font_family = escape(&theme.font_family),
+                font_size = theme.font_size,
+                title_font_size = theme.title_font_size,
+                line_height = theme.line_height(),
+                color = escape(&style.text),
                 lines = lines
             );
+        // the tooltip shows every line, even those the card wraps or truncates
+        let tooltip = std::iter::once(&self.id)
+            .chain(self.text_lines.iter())
+            .map(|line| escape(line))
+            .collect::<Vec<String>>()
+            .join("\n");
         format!("\
-        <rect id='{id}' stroke='black' stroke-width='2' x='{x}' y='{y}' height='{height}' width='{width}' fill='{fill}' />\
+        <g role='img' aria-label='{card_type} {label}'>\
+        <title>{tooltip}</title>\
+        <rect id='{id}' stroke='{stroke}' stroke-width='{stroke_width}' x='{x}' y='{y}' height='{height}' width='{width}' fill='{fill}' />\
         {text}\
+        </g>\
         ",
-        id=self.id,
+        card_type=self.card_type.name(),
+        label=escape(&self.id),
+        tooltip=tooltip,
+        id=self.xml_id,
+        stroke=escape(&style.stroke),
+        stroke_width=theme.card_stroke_width,
         x=self.origin.x,
         y=self.origin.y,
         width=self.width,
         height=self.height,
-        fill=fill,
+        fill=escape(&style.fill),
         text=text,
         )
     }
 
-    pub fn render(&self) -> String {
-        match self.card_type {
-            CardType::Job => self._render("#ffffff"),
-            CardType::Form => self._render("#ffffff"),
-            CardType::Command => self._render("#60b3f7"),
-            CardType::Event => self._render("#f7a660"),
-            CardType::View => self._render("#60f765"),
-        }
+    pub fn render(&self, theme: &Theme) -> String {
+        self._render(theme.card_style(&self.card_type), theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        let observed = wrap("description: wake up and make coffee", 20);
+        let expected = vec![
+            "description: wake up".to_string(),
+            "  and make coffee".to_string(),
+        ];
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_wrap_without_spaces() {
+        let observed = wrap("abcdefghij", 4);
+        let expected = vec![
+            "abcd".to_string(),
+            "  ef".to_string(),
+            "  gh".to_string(),
+            "  ij".to_string(),
+        ];
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("| 123 | done |", 8), "| 123 |\u{2026}");
+        assert_eq!(truncate("| 123 |", 8), "| 123 |");
+    }
+
+    #[test]
+    fn test_fit_text() {
+        let mut card = Card::new(
+            "AddCustomer".to_string(),
+            CardType::Command,
+            vec![
+                "Name: John".to_string(),
+                format!("Notes: {}", "lorem ipsum ".repeat(10)),
+            ],
+        );
+        let theme = Theme::default();
+        card.fit_text(300f64, 12.5, &theme);
+        assert_eq!(card.max_chars, 28);
+        assert_eq!(card.display_lines.len(), 7);
+        assert_eq!(card.display_lines[6], "  ipsum");
+        assert!(card.display_lines.iter().all(|l| l.chars().count() <= 28));
+        let (width, height) = card.text_size(12.5, &theme);
+        assert!(width <= 300f64);
+        assert_eq!(height, 9f64 * 16f64 + 25f64);
     }
 }
@@ -0,0 +1,78 @@
+use super::card::{Card, Point};
+use super::theme::Theme;
+use super::xml::escape;
+
+// a framed region around the cards of one bounded context
+pub struct ContextFrame {
+    pub id: String,
+    pub xml_id: String,
+    pub card_ids: Vec<String>,
+    pub origin: Point,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl ContextFrame {
+    pub fn new(id: String, card_ids: Vec<String>) -> Self {
+        ContextFrame {
+            id,
+            xml_id: String::new(),
+            card_ids,
+            origin: Point { x: 0f64, y: 0f64 },
+            width: 0f64,
+            height: 0f64,
+        }
+    }
+
+    pub fn set_dimensions(&mut self, cards: &[Card], pad: f64) {
+        let members = cards
+            .iter()
+            .filter(|c| self.card_ids.contains(&c.id))
+            .collect::<Vec<&Card>>();
+        if members.is_empty() {
+            return;
+        }
+        let margin = pad / 4f64;
+        let left = members.iter().map(|c| c.origin.x).fold(f64::MAX, f64::min);
+        let top = members.iter().map(|c| c.origin.y).fold(f64::MAX, f64::min);
+        let right = members
+            .iter()
+            .map(|c| c.origin.x + c.width)
+            .fold(f64::MIN, f64::max);
+        let bottom = members
+            .iter()
+            .map(|c| c.origin.y + c.height)
+            .fold(f64::MIN, f64::max);
+        self.origin = Point {
+            x: left - margin,
+            y: top - margin,
+        };
+        self.width = right - left + margin * 2f64;
+        self.height = bottom - top + margin * 2f64;
+    }
+
+    pub fn render(&self, theme: &Theme) -> String {
+        if self.card_ids.is_empty() {
+            return String::new();
+        }
+        format!("\
+        <g id='{xml_id}'>\
+        <rect {stroke} x='{x}' y='{y}' height='{height}' width='{width}' fill='none' />\
+        <text x='{x}' y='{y}' dx='{dx}' dy='{dy}' font-family='{font_family}' font-size='{font_size}' font-weight='bold' fill='{color}'>{id}</text>\
+        </g>\
+        ",
+        xml_id=self.xml_id,
+        id=escape(&self.id),
+        stroke=theme.context.attributes(),
+        dx=theme.font_size / 2f64,
+        dy=theme.line_height(),
+        font_family=escape(&theme.font_family),
+        font_size=theme.font_size,
+        color=escape(&theme.context.stroke),
+        x=self.origin.x,
+        y=self.origin.y,
+        width=self.width,
+        height=self.height,
+        )
+    }
+}
@@ -1,11 +1,55 @@
 use super::arrow::Arrow;
 use super::card::{Card, CardType};
+use super::context::ContextFrame;
+use super::route::{Port, Router, Side};
 use super::swimlane::Swimlane;
+use super::theme::Theme;
+use super::xml::{escape, sanitize_id, xml_ids};
+use std::collections::HashMap;
 
+#[derive(Debug)]
 pub struct SvgConfig {
     pub pad: f64,
     pub card_height: f64,
     pub card_width: f64,
+    pub max_card_width: f64,
+    pub top_lane_height: f64,
+    pub middle_lane_height: f64,
+    pub bottom_lane_height: f64,
+    pub arrowhead: f64,
+    pub text_inset: f64,
+}
+
+impl Default for SvgConfig {
+    fn default() -> Self {
+        SvgConfig {
+            pad: 150f64,
+            card_height: 150f64,
+            card_width: 300f64,
+            max_card_width: 600f64,
+            top_lane_height: 150f64,
+            middle_lane_height: 150f64,
+            bottom_lane_height: 150f64,
+            arrowhead: 15f64,
+            text_inset: 12.5,
+        }
+    }
+}
+
+pub fn row(card_type: &CardType) -> usize {
+    match card_type {
+        CardType::Form | CardType::Job => 0,
+        CardType::Command | CardType::View => 1,
+        CardType::Event => 2,
+    }
+}
+
+// downward flows may stack their cards in a single column
+pub fn shares_column(from: &CardType, to: &CardType) -> bool {
+    matches!(
+        (from, to),
+        (CardType::Form | CardType::Job, CardType::Command) | (CardType::Command, CardType::Event)
+    )
 }
 
 pub struct SvgDocument {
@@ -15,104 +59,495 @@ pub struct SvgDocument {
     pub cards: Vec<Card>,
     pub arrows: Vec<Arrow>,
     pub swimlane: Swimlane,
+    pub contexts: Vec<ContextFrame>,
+    pub theme: Theme,
+    pub arrowhead: f64,
+    pub title: String,
+    // the cards that start a slice, the first after each `# ----` heading
+    pub slices: Vec<usize>,
+    // the x positions between slices, and between columns, where the
+    // timeline may be split
+    pub slice_breaks: Vec<f64>,
+    pub column_breaks: Vec<f64>,
+    // scale to the width of the container instead of a fixed size
+    pub fit: bool,
 }
 
 impl SvgDocument {
-    pub fn set_dimensions(&mut self, config: &SvgConfig) {
-        self.height = config.card_height * 3f64 + config.pad * 6f64;
-        self.width =
-            (config.pad * 2f64) + (config.pad + config.card_width) * self.cards.len() as f64;
-        self.swimlane.top = config.card_height + config.pad * 2f64;
-        self.swimlane.bottom = config.card_height * 2f64 + config.pad * 4f64;
-        self.swimlane.width = self.width;
-        let mut x_pos = config.pad;
-        for card in self.cards.iter_mut() {
-            match card.card_type {
-                CardType::Form => {
-                    card.set_origin(x_pos, config.pad);
-                    card.set_dimensions(config.card_width, config.card_height, config.pad);
-                    x_pos += config.card_width + config.pad;
-                }
-                CardType::Job => {
-                    card.set_origin(x_pos, config.pad);
-                    card.set_dimensions(config.card_width, config.card_height, config.pad);
-                    x_pos += config.card_width + config.pad;
-                }
-                CardType::Command => {
-                    card.set_origin(x_pos, config.card_height + config.pad * 3f64);
-                    card.set_dimensions(config.card_width, config.card_height, config.pad);
-                    x_pos += config.card_width + config.pad;
-                }
-                CardType::View => {
-                    card.set_origin(x_pos, config.card_height + config.pad * 3f64);
-                    card.set_dimensions(config.card_width, config.card_height, config.pad);
-                    x_pos += config.card_width + config.pad;
-                }
-                CardType::Event => {
-                    card.set_origin(x_pos, config.card_height * 3f64 + config.pad * 5f64);
-                    card.set_dimensions(config.card_width, config.card_height, config.pad);
-                    x_pos += config.card_width + config.pad;
+    pub fn new(theme: Theme) -> Self {
+        SvgDocument {
+            id: "eml".to_string(),
+            width: 0f64,
+            height: 0f64,
+            cards: Vec::new(),
+            arrows: Vec::new(),
+            swimlane: Swimlane::new(),
+            contexts: Vec::new(),
+            theme,
+            arrowhead: SvgConfig::default().arrowhead,
+            title: "Event model".to_string(),
+            slices: Vec::new(),
+            slice_breaks: Vec::new(),
+            column_breaks: Vec::new(),
+            fit: false,
+        }
+    }
+
+    // a summary of the model for screen readers
+    pub fn describe(&self) -> String {
+        let count = |card_type: &CardType| {
+            self.cards
+                .iter()
+                .filter(|c| c.card_type == *card_type)
+                .count()
+        };
+        let plural =
+            |n: usize, name: &str| format!("{} {}{}", n, name, if n == 1 { "" } else { "s" });
+        let counts = [
+            CardType::Form,
+            CardType::Job,
+            CardType::Command,
+            CardType::Event,
+            CardType::View,
+        ]
+        .iter()
+        .map(|t| (count(t), t.name()))
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, name)| plural(n, name))
+        .collect::<Vec<String>>();
+        let mut description = format!(
+            "An event model of {} connected by {}.",
+            match counts.split_last() {
+                None => "no cards".to_string(),
+                Some((last, [])) => last.clone(),
+                Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+            },
+            plural(self.arrows.len(), "flow")
+        );
+        if !self.contexts.is_empty() {
+            let names = self
+                .contexts
+                .iter()
+                .map(|c| c.id.as_str())
+                .collect::<Vec<&str>>();
+            description.push_str(&format!(" Contexts: {}.", names.join(", ")));
+        }
+        description
+    }
+
+    // place cards on a timeline in the order they were declared. a card moves
+    // to the next column unless a downward flow from the current column lets
+    // it stack beneath its source, and it never lands left of its sources
+    pub fn layout_columns(&self) -> Vec<usize> {
+        let index = self
+            .cards
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.as_str(), i))
+            .collect::<HashMap<&str, usize>>();
+        let mut sources = vec![Vec::new(); self.cards.len()];
+        for arrow in self.arrows.iter() {
+            if let (Some(&from), Some(&to)) = (
+                index.get(arrow.begin_at.id.as_str()),
+                index.get(arrow.end_at.id.as_str()),
+            ) {
+                if from < to {
+                    sources[to].push(from);
                 }
+            }
+        }
+        let contexts = self
+            .cards
+            .iter()
+            .map(|card| {
+                self.contexts
+                    .iter()
+                    .position(|c| c.card_ids.contains(&card.id))
+            })
+            .collect::<Vec<Option<usize>>>();
+        let mut columns: Vec<usize> = Vec::with_capacity(self.cards.len());
+        let mut row_next = [0usize; 3];
+        for (i, card) in self.cards.iter().enumerate() {
+            let row = row(&card.card_type);
+            let mut column = row_next[row];
+            if let Some(&timeline) = columns.last() {
+                // a context's columns hold only its own cards, so that its
+                // frame takes in no card from outside
+                let stacks = contexts[i] == contexts[i - 1]
+                    && sources[i].iter().any(|&from| {
+                        columns[from] == timeline
+                            && shares_column(&self.cards[from].card_type, &card.card_type)
+                    });
+                column = column.max(if stacks { timeline } else { timeline + 1 });
+            }
+            for &from in sources[i].iter() {
+                let step = !shares_column(&self.cards[from].card_type, &card.card_type);
+                column = column.max(columns[from] + step as usize);
+            }
+            columns.push(column);
+            row_next[row] = column + 1;
+        }
+        columns
+    }
+
+    // element ids that cannot clash with each other or with the ids of the
+    // document's own elements
+    pub fn assign_xml_ids(&mut self) {
+        let cards = xml_ids("card-", self.cards.iter().map(|c| c.id.as_str()));
+        for (card, xml_id) in self.cards.iter_mut().zip(cards) {
+            card.xml_id = xml_id;
+        }
+        let arrows = xml_ids("", self.arrows.iter().map(|a| a.id.as_str()));
+        for (arrow, xml_id) in self.arrows.iter_mut().zip(arrows) {
+            arrow.xml_id = xml_id;
+        }
+        let contexts = xml_ids("context_", self.contexts.iter().map(|c| c.id.as_str()));
+        for (context, xml_id) in self.contexts.iter_mut().zip(contexts) {
+            context.xml_id = xml_id;
+        }
+    }
+
+    pub fn set_dimensions(&mut self, config: &SvgConfig) -> Result<(), String> {
+        self.assign_xml_ids();
+        // rows grow to fit their tallest card
+        let mut row_heights = [
+            config.top_lane_height,
+            config.middle_lane_height,
+            config.bottom_lane_height,
+        ]
+        .map(|height| height.max(config.card_height));
+        for card in self.cards.iter_mut() {
+            card.fit_text(config.max_card_width, config.text_inset, &self.theme);
+            let (_, text_height) = card.text_size(config.text_inset, &self.theme);
+            let row = row(&card.card_type);
+            row_heights[row] = row_heights[row].max(text_height);
+        }
+        let top_y = config.pad;
+        self.swimlane.top = top_y + row_heights[0] + config.pad;
+        let middle_y = self.swimlane.top + config.pad;
+        self.swimlane.bottom = middle_y + row_heights[1] + config.pad;
+        let bottom_y = self.swimlane.bottom + config.pad;
+        self.height = bottom_y + row_heights[2] + config.pad;
+        // columns are as wide as their widest card
+        let columns = self.layout_columns();
+        let mut column_widths = vec![config.card_width; columns.iter().max().map_or(0, |c| c + 1)];
+        for (card, &column) in self.cards.iter().zip(columns.iter()) {
+            let (text_width, _) = card.text_size(config.text_inset, &self.theme);
+            column_widths[column] = column_widths[column].max(text_width);
+        }
+        let mut column_x = Vec::with_capacity(column_widths.len());
+        let mut x_pos = config.pad;
+        for width in column_widths.iter() {
+            column_x.push(x_pos);
+            x_pos += width + config.pad;
+        }
+        for (card, &column) in self.cards.iter_mut().zip(columns.iter()) {
+            let (text_width, text_height) = card.text_size(config.text_inset, &self.theme);
+            let width = config.card_width.max(text_width);
+            let height = config.card_height.max(text_height);
+            let y_pos = match row(&card.card_type) {
+                0 => top_y,
+                1 => middle_y,
+                _ => bottom_y,
             };
+            card.set_origin(
+                column_x[column] + (column_widths[column] - width) / 2f64,
+                y_pos,
+            );
+            card.set_dimensions(width, height, config.text_inset);
+        }
+        self.width = x_pos + config.pad;
+        self.column_breaks = std::iter::once(0f64)
+            .chain(column_x.iter().skip(1).map(|x| x - config.pad / 2f64))
+            .chain(std::iter::once(self.width))
+            .collect();
+        // a slice that starts in the last column of the one before has no
+        // clean break
+        self.slice_breaks = vec![0f64];
+        for &first in self.slices.iter() {
+            if 0 < first && first < columns.len() && columns[first - 1] < columns[first] {
+                self.slice_breaks.push(self.column_breaks[columns[first]]);
+            }
         }
+        self.slice_breaks.push(self.width);
+        self.slice_breaks.dedup();
+        self.swimlane.width = self.width;
         for arrow in self.arrows.iter_mut() {
             arrow.set_dimensions(&self.cards);
         }
+        self.arrowhead = config.arrowhead;
+        self.route_arrows()?;
+        for context in self.contexts.iter_mut() {
+            context.set_dimensions(&self.cards, config.pad);
+        }
+        Ok(())
+    }
+
+    // arrows sharing a side of a card are spread along it, ordered by where
+    // their other end is so they do not cross on the way out
+    pub fn assign_ports(&self) -> Result<Vec<(Port, Port)>, String> {
+        // (position of the other end, arrow index, whether this is the arrow's end)
+        type PortUse = (f64, usize, bool);
+        let mut groups: HashMap<(&str, Side), Vec<PortUse>> = HashMap::new();
+        for (i, arrow) in self.arrows.iter().enumerate() {
+            let (begin_side, end_side) = arrow.get_sides()?;
+            for (card, other, side, is_end) in [
+                (&arrow.begin_at, &arrow.end_at, begin_side, false),
+                (&arrow.end_at, &arrow.begin_at, end_side, true),
+            ] {
+                let key = match side {
+                    Side::Top | Side::Bottom => other.top_anchor().x,
+                    Side::Left | Side::Right => other.left_anchor().y,
+                };
+                groups
+                    .entry((card.id.as_str(), side))
+                    .or_default()
+                    .push((key, i, is_end));
+            }
+        }
+        let mut fractions = vec![(0.5f64, 0.5f64); self.arrows.len()];
+        for members in groups.values_mut() {
+            members.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let count = members.len() as f64;
+            for (rank, (_, i, is_end)) in members.iter().enumerate() {
+                let fraction = (rank as f64 + 1f64) / (count + 1f64);
+                if *is_end {
+                    fractions[*i].1 = fraction;
+                } else {
+                    fractions[*i].0 = fraction;
+                }
+            }
+        }
+        self.arrows
+            .iter()
+            .zip(fractions.iter())
+            .map(|(arrow, (begin_fraction, end_fraction))| {
+                let (begin_side, end_side) = arrow.get_sides()?;
+                Ok((
+                    Port {
+                        point: arrow.begin_at.side_anchor(&begin_side, *begin_fraction),
+                        side: begin_side,
+                    },
+                    Port {
+                        point: arrow.end_at.side_anchor(&end_side, *end_fraction),
+                        side: end_side,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    pub fn route_arrows(&mut self) -> Result<(), String> {
+        let ports = self.assign_ports()?;
+        let mut router = Router::new(&self.cards, self.arrowhead * 2f64, self.arrowhead);
+        for (arrow, (start, end)) in self.arrows.iter_mut().zip(ports) {
+            arrow.route(&mut router, start, end);
+        }
+        Ok(())
     }
 
-    pub fn render(self) -> String {
+    fn defs(&self) -> String {
         format!(
             "\
-            <svg xmlns='http://www.w3.org/2000/svg' width='{width}' height='{height}'>\
-              <defs>\
-                <marker id='triangle' viewBox='0 0 10 10' refX='0' refY='5' markerUnits='strokeWidth' markerWidth='10' markerHeight='8' orient='auto'>\
-                  <path d='M 0 0 L 10 5 L 0 10 z' />\
-                </marker>\
-              </defs>\
-              <g id='swimlane' stroke='black' stroke-width='3' >\
-                <line id='swimlane_top' x1='0' y1='{top}' x2='{width}' y2='{top}' />\
-                <line id='swimlane_bottom' x1='0' y1='{bottom}' x2='{width}' y2='{bottom}' />\
-              </g>\
-              {arrows}\
-              {cards}\
-            </svg>\
+            <defs>\
+              <marker id='triangle' viewBox='0 0 10 10' refX='0' refY='5' markerUnits='strokeWidth' markerWidth='{marker_width}' markerHeight='{marker_height}' orient='auto'>\
+                <path d='M 0 0 L 10 5 L 0 10 z' fill='{arrowhead}' />\
+              </marker>\
+            </defs>\
+            ",
+            arrowhead = escape(&self.theme.arrow.stroke),
+            marker_width = self.arrowhead * 2f64 / 3f64,
+            marker_height = self.arrowhead * 8f64 / 15f64,
+        )
+    }
+
+    // the lanes and everything drawn on them
+    fn content(&self) -> String {
+        format!(
+            "\
+            <g id='swimlane' {lane} >\
+              <line id='swimlane_top' x1='0' y1='{top}' x2='{width}' y2='{top}' />\
+              <line id='swimlane_bottom' x1='0' y1='{bottom}' x2='{width}' y2='{bottom}' />\
+            </g>\
+            {contexts}\
+            {arrows}\
+            {cards}\
             ",
             width = self.width,
-            height = self.height,
+            lane = self.theme.lane.attributes(),
             top = self.swimlane.top,
             bottom = self.swimlane.bottom,
+            contexts = self
+                .contexts
+                .iter()
+                .map(|el| { el.render(&self.theme) })
+                .collect::<Vec<String>>()
+                .join(""),
             cards = self
                 .cards
                 .iter()
-                .map(|el| { el.render() })
+                .map(|el| { el.render(&self.theme) })
                 .collect::<Vec<String>>()
                 .join(""),
             arrows = self
                 .arrows
                 .iter()
-                .map(|el| { el.render() })
+                .map(|el| { el.render(&self.theme) })
                 .collect::<Vec<String>>()
                 .join(""),
         )
     }
+
+    pub fn render(&self) -> String {
+        let background = match &self.theme.background {
+            Some(color) => format!(
+                "<rect id='background' width='100%' height='100%' fill='{}' />",
+                escape(color)
+            ),
+            None => String::new(),
+        };
+        let size = if self.fit {
+            "width='100%' preserveAspectRatio='xMidYMin meet'".to_string()
+        } else {
+            format!("width='{}' height='{}'", self.width, self.height)
+        };
+        format!(
+            "\
+            <svg xmlns='http://www.w3.org/2000/svg' id='{id}' {size} viewBox='0 0 {width} {height}' role='img' aria-labelledby='eml_title eml_desc'>\
+              <title id='eml_title'>{title}</title>\
+              <desc id='eml_desc'>{desc}</desc>\
+              {defs}\
+              {background}\
+              {content}\
+            </svg>\
+            ",
+            id = sanitize_id(&self.id),
+            size = size,
+            width = self.width,
+            height = self.height,
+            title = escape(&self.title),
+            desc = escape(&self.describe()),
+            defs = self.defs(),
+            background = background,
+            content = self.content(),
+        )
+    }
+
+    // group whole slices into pages no wider than page_width. a slice wider
+    // than that is split between its columns, and a column wider than that
+    // gets a page of its own
+    pub fn paginate(&self, page_width: f64) -> Vec<(f64, f64)> {
+        let mut pieces = Vec::new();
+        for pair in self.slice_breaks.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            if right - left <= page_width {
+                pieces.push((left, right));
+                continue;
+            }
+            let columns = self
+                .column_breaks
+                .iter()
+                .filter(|x| left < **x && **x < right);
+            let breaks = std::iter::once(&left)
+                .chain(columns)
+                .chain(std::iter::once(&right))
+                .collect::<Vec<&f64>>();
+            pieces.extend(breaks.windows(2).map(|pair| (*pair[0], *pair[1])));
+        }
+        let mut pages: Vec<(f64, f64)> = Vec::new();
+        for (left, right) in pieces {
+            match pages.last_mut() {
+                Some(page) if right - page.0 <= page_width => page.1 = right,
+                _ => pages.push((left, right)),
+            }
+        }
+        pages
+    }
+
+    // one svg per page, each starting with the lane labels
+    pub fn render_pages(&self, page_width: f64) -> Result<Vec<String>, String> {
+        if !(page_width.is_finite() && page_width > 0f64) {
+            return Err(format!(
+                "page width must be a positive number, not {}",
+                page_width
+            ));
+        }
+        let gutter = self.theme.line_height() * 3f64;
+        let pages = self.paginate(page_width);
+        let count = pages.len();
+        let lanes = [
+            ("Forms & Jobs", 0f64, self.swimlane.top),
+            ("Commands & Views", self.swimlane.top, self.swimlane.bottom),
+            ("Events", self.swimlane.bottom, self.height),
+        ];
+        let color = self.theme.background.as_deref().unwrap_or("#ffffff");
+        Ok(pages
+            .iter()
+            .enumerate()
+            .map(|(i, (left, right))| {
+                let x = left - gutter;
+                let labels = lanes
+                    .iter()
+                    .map(|(label, top, bottom)| {
+                        format!(
+                            "<text transform='translate({cx} {cy}) rotate(-90)' text-anchor='middle' dominant-baseline='middle' font-family='{font_family}' font-size='{font_size}' font-weight='bold' fill='{fill}'>{label}</text>",
+                            cx = x + gutter / 2f64,
+                            cy = (top + bottom) / 2f64,
+                            font_family = escape(&self.theme.font_family),
+                            font_size = self.theme.font_size,
+                            fill = escape(&self.theme.lane.stroke),
+                            label = escape(label),
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("");
+                format!(
+                    "\
+                    <svg xmlns='http://www.w3.org/2000/svg' width='{width}' height='{height}' viewBox='{x} 0 {width} {height}'>\
+                      <title>{title} ({page} of {count})</title>\
+                      {defs}\
+                      <rect x='{x}' y='0' width='{width}' height='{height}' fill='{color}' />\
+                      {content}\
+                      <g id='lane_labels'>\
+                        <rect x='{x}' y='0' width='{gutter}' height='{height}' fill='{color}' />\
+                        <g {lane} >\
+                          <line x1='{x}' y1='{top}' x2='{left}' y2='{top}' />\
+                          <line x1='{x}' y1='{bottom}' x2='{left}' y2='{bottom}' />\
+                        </g>\
+                        {labels}\
+                      </g>\
+                    </svg>\
+                    ",
+                    x = x,
+                    left = left,
+                    width = right - left + gutter,
+                    height = self.height,
+                    gutter = gutter,
+                    title = escape(&self.title),
+                    page = i + 1,
+                    count = count,
+                    defs = self.defs(),
+                    color = escape(color),
+                    content = self.content(),
+                    lane = self.theme.lane.attributes(),
+                    top = self.swimlane.top,
+                    bottom = self.swimlane.bottom,
+                    labels = labels,
+                )
+            })
+            .collect())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::newid;
+    use crate::svg::card::TextOverflow;
 
     #[test]
     fn test_document() {
-        let expected = "<svg xmlns='http://www.w3.org/2000/svg' width='1650' height='1350'><defs><marker id='triangle' viewBox='0 0 10 10' refX='0' refY='5' markerUnits='strokeWidth' markerWidth='10' markerHeight='8' orient='auto'><path d='M 0 0 L 10 5 L 0 10 z' /></marker></defs><g id='swimlane' stroke='black' stroke-width='3' ><line id='swimlane_top' x1='0' y1='450' x2='1650' y2='450' /><line id='swimlane_bottom' x1='0' y1='900' x2='1650' y2='900' /></g><path d='M 450 225 Q 750 225 750 585' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' /><path d='M 750 750 Q 750 1275 1035 1275' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' /><rect id='form1' stroke='black' stroke-width='2' x='150' y='150' height='150' width='300' fill='#ffffff' /><text transform='translate(162.5 162.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' ><tspan x='0' dy='1rem' font-size='larger' font-weight='bold'>form1</tspan><tspan x='0' dy='1rem'>==========</tspan><tspan x='0' dy='1rem'>Test Form</tspan></text><rect id='cmd1' stroke='black' stroke-width='2' x='600' y='600' height='150' width='300' fill='#60b3f7' /><text transform='translate(612.5 612.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' ><tspan x='0' dy='1rem' font-size='larger' font-weight='bold'>cmd1</tspan><tspan x='0' dy='1rem'>==========</tspan><tspan x='0' dy='1rem'>Test Cmd</tspan></text><rect id='evt1' stroke='black' stroke-width='2' x='1050' y='1200' height='150' width='300' fill='#f7a660' /><text transform='translate(1062.5 1212.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' ><tspan x='0' dy='1rem' font-size='larger' font-weight='bold'>evt1</tspan><tspan x='0' dy='1rem'>==========</tspan><tspan x='0' dy='1rem'>Test Event</tspan><tspan x='0' dy='1rem'>+ foo: str</tspan><tspan x='0' dy='1rem'>+ bar: str</tspan></text></svg>".to_string();
-        let mut doc = SvgDocument {
-            id: newid(),
-            width: 1000f64,
-            height: 1000f64,
-            cards: Vec::new(),
-            arrows: Vec::new(),
-            swimlane: Swimlane::new(),
-        };
+        let expected = "<svg xmlns='http://www.w3.org/2000/svg' id='eml' width='750' height='1350' viewBox='0 0 750 1350' role='img' aria-labelledby='eml_title eml_desc'><title id='eml_title'>Event model</title><desc id='eml_desc'>An event model of 1 form, 1 command and 1 event connected by 2 flows.</desc><defs><marker id='triangle' viewBox='0 0 10 10' refX='0' refY='5' markerUnits='strokeWidth' markerWidth='10' markerHeight='8' orient='auto'><path d='M 0 0 L 10 5 L 0 10 z' fill='black' /></marker></defs><g id='swimlane' stroke='black' stroke-width='3' ><line id='swimlane_top' x1='0' y1='450' x2='750' y2='450' /><line id='swimlane_bottom' x1='0' y1='900' x2='750' y2='900' /></g><path id='flow-form1-cmd1' d='M 300 300 L 300 585' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' role='img' aria-label='flow from form1 to cmd1' /><path id='flow-cmd1-evt1' d='M 300 750 L 300 1035' stroke='black' stroke-width='2' fill='none' marker-end='url(#triangle)' role='img' aria-label='flow from cmd1 to evt1' /><g role='img' aria-label='form form1'><title>form1\nTest Form</title><rect id='card-form1' stroke='black' stroke-width='2' x='150' y='150' height='150' width='300' fill='#ffffff' /><text transform='translate(162.5 162.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' font-size='16' fill='black' ><tspan x='0' dy='16' font-size='19.2' font-weight='bold'>form1</tspan><tspan x='0' dy='16'>==========</tspan><tspan x='0' dy='16'>Test Form</tspan></text></g><g role='img' aria-label='command cmd1'><title>cmd1\nTest Cmd</title><rect id='card-cmd1' stroke='black' stroke-width='2' x='150' y='600' height='150' width='300' fill='#60b3f7' /><text transform='translate(162.5 612.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' font-size='16' fill='black' ><tspan x='0' dy='16' font-size='19.2' font-weight='bold'>cmd1</tspan><tspan x='0' dy='16'>==========</tspan><tspan x='0' dy='16'>Test Cmd</tspan></text></g><g role='img' aria-label='event evt1'><title>evt1\nTest Event\n+ foo: str\n+ bar: str</title><rect id='card-evt1' stroke='black' stroke-width='2' x='150' y='1050' height='150' width='300' fill='#f7a660' /><text transform='translate(162.5 1062.5)' x='0' y='0' style='white-space: pre;' font-family='monospace' font-size='16' fill='black' ><tspan x='0' dy='16' font-size='19.2' font-weight='bold'>evt1</tspan><tspan x='0' dy='16'>==========</tspan><tspan x='0' dy='16'>Test Event</tspan><tspan x='0' dy='16'>+ foo: str</tspan><tspan x='0' dy='16'>+ bar: str</tspan></text></g></svg>".to_string();
+        let mut doc = SvgDocument::new(Theme::default());
         doc.cards.push(Card::new(
             "form1".to_string(),
             CardType::Form,
@@ -140,14 +575,402 @@ mod tests {
             doc.cards.iter().find(|c| c.id == "cmd1").unwrap().clone(),
             doc.cards.iter().find(|c| c.id == "evt1").unwrap().clone(),
         ));
-        let config = SvgConfig {
-            pad: 150f64,
-            card_width: 300f64,
-            card_height: 150f64,
-        };
-        doc.set_dimensions(&config);
+        let config = SvgConfig::default();
+        doc.set_dimensions(&config).unwrap();
+        let observed = doc.render();
+
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_document_fits_cards() {
+        let mut doc = SvgDocument::new(Theme::default());
+        doc.cards.push(Card::new(
+            "cmd1".to_string(),
+            CardType::Command,
+            vec!["Description: a field value that is wider than the card".to_string()],
+        ));
+        doc.cards.push(Card::new(
+            "evt1".to_string(),
+            CardType::Event,
+            (0..10).map(|i| format!("field{}: x", i)).collect(),
+        ));
+        let config = SvgConfig::default();
+        doc.set_dimensions(&config).unwrap();
+        let (cmd, evt) = (&doc.cards[0], &doc.cards[1]);
+        assert_eq!(cmd.width, 543.4);
+        assert_eq!(cmd.height, 150f64);
+        assert_eq!(evt.origin.x, cmd.origin.x + cmd.width + 150f64);
+        assert_eq!(evt.height, 12f64 * 16f64 + 25f64);
+        assert_eq!(doc.height, evt.origin.y + evt.height + 150f64);
+        assert_eq!(doc.width, evt.origin.x + 300f64 + 300f64);
+    }
+
+    #[test]
+    fn test_document_hostile_text() {
+        let corpus = vec![
+            "a < b & \"c\"",
+            "'; DROP TABLE cards; --",
+            "</tspan></text><script>alert(1)</script>",
+            "]]><![CDATA[",
+            "<!-- -->",
+            "&amp; &#x41; &nbsp;",
+            "tab\tand\rreturn",
+            "nul\u{0}bell\u{7}esc\u{1b}",
+            "1 starts with a digit",
+            "unicode \u{1f600} \u{202e}rtl",
+            "",
+        ];
+        for text in corpus {
+            let mut doc = SvgDocument::new(Theme::default());
+            doc.cards.push(Card::new(
+                text.to_string(),
+                CardType::Form,
+                vec![text.to_string(), format!("{}: {}", text, text)],
+            ));
+            doc.cards.push(Card::new(
+                "cmd1".to_string(),
+                CardType::Command,
+                vec![text.to_string()],
+            ));
+            doc.arrows
+                .push(Arrow::new(doc.cards[0].clone(), doc.cards[1].clone()));
+            doc.contexts.push(ContextFrame::new(
+                text.to_string(),
+                vec![text.to_string(), "cmd1".to_string()],
+            ));
+            let config = SvgConfig::default();
+            doc.set_dimensions(&config).unwrap();
+            let observed = doc.render();
+            let xml = roxmltree::Document::parse(&observed)
+                .unwrap_or_else(|e| panic!("{:?} produced invalid xml: {}", text, e));
+            // whatever survives unchanged must read back as the original text
+            if !text.is_empty() && !text.chars().any(char::is_control) {
+                let texts = xml
+                    .descendants()
+                    .filter(|n| n.has_tag_name("tspan"))
+                    .filter_map(|n| n.text())
+                    .collect::<Vec<&str>>();
+                assert!(texts.contains(&text), "{:?} not found in {:?}", text, texts);
+            }
+        }
+    }
+
+    #[test]
+    fn test_document_ids() {
+        let mut doc = SvgDocument::new(Theme::default());
+        for id in [
+            "triangle",
+            "swimlane",
+            "background",
+            "eml_title",
+            "a b",
+            "a_b",
+        ] {
+            doc.cards
+                .push(Card::new(id.to_string(), CardType::Form, vec![]));
+        }
+        doc.contexts.push(ContextFrame::new(
+            "a b".to_string(),
+            vec!["a b".to_string()],
+        ));
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        let observed = doc.render();
+        let xml = roxmltree::Document::parse(&observed).unwrap();
+        let ids = xml
+            .descendants()
+            .filter_map(|n| n.attribute("id"))
+            .collect::<Vec<&str>>();
+        for (i, id) in ids.iter().enumerate() {
+            assert!(!ids[..i].contains(id), "{} is not unique", id);
+        }
+        let marker = xml
+            .descendants()
+            .find(|n| n.attribute("id") == Some("triangle"));
+        assert!(marker.unwrap().has_tag_name("marker"));
+        assert!(ids.contains(&"card-a_b") && ids.contains(&"card-a_b-2"));
+    }
+
+    fn timeline_document(cards: Vec<(&str, CardType)>, flows: Vec<(&str, &str)>) -> SvgDocument {
+        let mut doc = SvgDocument::new(Theme::default());
+        for (id, card_type) in cards {
+            doc.cards.push(Card::new(id.to_string(), card_type, vec![]));
+        }
+        for (from, to) in flows {
+            doc.arrows.push(Arrow::new(
+                doc.cards.iter().find(|c| c.id == from).unwrap().clone(),
+                doc.cards.iter().find(|c| c.id == to).unwrap().clone(),
+            ));
+        }
+        doc
+    }
+
+    #[test]
+    fn test_layout_columns() {
+        let doc = timeline_document(
+            vec![
+                ("CustomerForm", CardType::Form),
+                ("AddCustomer", CardType::Command),
+                ("CustomerAdded", CardType::Event),
+                ("AccountsToAdd", CardType::View),
+                ("ProcessAccountsToAdd", CardType::Job),
+                ("AddAccount", CardType::Command),
+                ("AccountAdded", CardType::Event),
+                ("UsersToAdd", CardType::View),
+                ("ProcessUsersToAdd", CardType::Job),
+                ("AddUser", CardType::Command),
+                ("UserAdded", CardType::Event),
+            ],
+            vec![
+                ("CustomerForm", "AddCustomer"),
+                ("AddCustomer", "CustomerAdded"),
+                ("CustomerAdded", "AccountsToAdd"),
+                ("AccountsToAdd", "ProcessAccountsToAdd"),
+                ("ProcessAccountsToAdd", "AddAccount"),
+                ("AddAccount", "AccountAdded"),
+                ("CustomerAdded", "UsersToAdd"),
+                ("UsersToAdd", "ProcessUsersToAdd"),
+                ("ProcessUsersToAdd", "AddUser"),
+                ("AddUser", "UserAdded"),
+            ],
+        );
+        assert_eq!(doc.layout_columns(), vec![0, 0, 0, 1, 2, 2, 2, 3, 4, 4, 4]);
+    }
+
+    #[test]
+    fn test_layout_columns_without_flow() {
+        let doc = timeline_document(
+            vec![
+                ("form1", CardType::Form),
+                ("cmd1", CardType::Command),
+                ("evt1", CardType::Event),
+                ("cmd2", CardType::Command),
+            ],
+            vec![("cmd1", "evt1")],
+        );
+        assert_eq!(doc.layout_columns(), vec![0, 1, 1, 2]);
+    }
+
+    #[test]
+    fn test_layout_columns_contexts() {
+        let mut doc = timeline_document(
+            vec![
+                ("Before", CardType::Form),
+                ("Go", CardType::Command),
+                ("Done", CardType::Event),
+                ("Start", CardType::Form),
+                ("After", CardType::Command),
+            ],
+            vec![("Before", "Go"), ("Go", "Done"), ("Start", "After")],
+        );
+        doc.contexts.push(ContextFrame::new(
+            "Billing".to_string(),
+            vec!["Go".to_string(), "Done".to_string(), "Start".to_string()],
+        ));
+        // neither outside card stacks onto a member of the context
+        assert_eq!(doc.layout_columns(), vec![0, 1, 1, 2, 3]);
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        let frame = &doc.contexts[0];
+        for card in [&doc.cards[0], &doc.cards[4]] {
+            let inside = frame.origin.x < card.origin.x + card.width
+                && card.origin.x < frame.origin.x + frame.width;
+            assert!(!inside, "{} is inside the Billing frame", card.id);
+        }
+    }
+
+    #[test]
+    fn test_layout_columns_centred() {
+        let mut doc = timeline_document(
+            vec![("form1", CardType::Form), ("cmd1", CardType::Command)],
+            vec![("form1", "cmd1")],
+        );
+        doc.cards[1].text_lines = vec!["x".repeat(40)];
+        let config = SvgConfig::default();
+        doc.set_dimensions(&config).unwrap();
+        let (form, cmd) = (&doc.cards[0], &doc.cards[1]);
+        assert_eq!(cmd.origin.x, 150f64);
+        assert_eq!(form.top_anchor().x, cmd.top_anchor().x);
+        assert!(doc.arrows[0].is_stacked());
+    }
+
+    #[test]
+    fn test_assign_ports_spreads_shared_sides() {
+        let mut doc = timeline_document(
+            vec![
+                ("evt1", CardType::Event),
+                ("view1", CardType::View),
+                ("view2", CardType::View),
+            ],
+            vec![("evt1", "view1"), ("evt1", "view2")],
+        );
+        let config = SvgConfig::default();
+        doc.set_dimensions(&config).unwrap();
+        let ports = doc.assign_ports().unwrap();
+        let evt = &doc.cards[0];
+        assert_eq!(ports[0].0.point.y, evt.origin.y + 50f64);
+        assert_eq!(ports[1].0.point.y, evt.origin.y + 100f64);
+        assert_eq!(ports[0].1.point.x, doc.cards[1].top_anchor().x);
+        for arrow in doc.arrows.iter() {
+            assert!(arrow.points.len() >= 2);
+        }
+    }
+
+    #[test]
+    fn test_illegal_flow() {
+        let mut doc = timeline_document(
+            vec![("form1", CardType::Form), ("evt1", CardType::Event)],
+            vec![("form1", "evt1")],
+        );
+        let observed = doc.set_dimensions(&SvgConfig::default()).unwrap_err();
+        assert_eq!(observed, "a flow cannot go from form form1 to event evt1");
+    }
+
+    #[test]
+    fn test_document_theme() {
+        let mut doc = timeline_document(
+            vec![("cmd1", CardType::Command), ("evt1", CardType::Event)],
+            vec![("cmd1", "evt1")],
+        );
+        doc.theme = Theme::dark();
+        let config = SvgConfig::default();
+        doc.set_dimensions(&config).unwrap();
+        let observed = doc.render();
+        assert!(
+            observed.contains("<rect id='background' width='100%' height='100%' fill='#1e1e1e' />")
+        );
+        assert!(observed.contains("stroke='#9cdcfe' stroke-width='2' x='150' y='600' height='150' width='300' fill='#1f4e79'"));
+        assert!(observed.contains("<g id='swimlane' stroke='#808080' stroke-width='3' >"));
+        assert!(observed
+            .contains("stroke='#d4d4d4' stroke-width='2' fill='none' marker-end='url(#triangle)'"));
+        assert!(!observed.contains("black"));
+        roxmltree::Document::parse(&observed).unwrap();
+    }
+
+    #[test]
+    fn test_document_accessibility() {
+        let mut doc = timeline_document(
+            vec![("cmd1", CardType::Command), ("evt1", CardType::Event)],
+            vec![("cmd1", "evt1")],
+        );
+        doc.cards[1].text_lines = vec![format!("notes: {}", "x".repeat(100))];
+        doc.cards[1].text_overflow = TextOverflow::Truncate;
+        doc.title = "Orders".to_string();
+        doc.fit = true;
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        let view_box = format!("0 0 {} {}", doc.width, doc.height);
         let observed = doc.render();
+        let xml = roxmltree::Document::parse(&observed).unwrap();
+        let root = xml.root_element();
+        assert_eq!(root.attribute("width"), Some("100%"));
+        assert_eq!(root.attribute("height"), None);
+        assert_eq!(root.attribute("viewBox"), Some(view_box.as_str()));
+        let title = root.first_element_child().unwrap();
+        assert_eq!(title.text(), Some("Orders"));
+        let tooltips = xml
+            .descendants()
+            .filter(|n| n.has_tag_name("title") && n.parent() != Some(root))
+            .filter_map(|n| n.text())
+            .collect::<Vec<&str>>();
+        let expected = vec![
+            "cmd1".to_string(),
+            format!("evt1\nnotes: {}", "x".repeat(100)),
+        ];
+        assert_eq!(expected, tooltips);
+        assert!(xml
+            .descendants()
+            .any(|n| n.attribute("aria-label") == Some("flow from cmd1 to evt1")));
+        assert!(xml
+            .descendants()
+            .any(|n| n.attribute("aria-label") == Some("event evt1")));
+    }
 
+    #[test]
+    fn test_describe() {
+        let mut doc = timeline_document(
+            vec![
+                ("form1", CardType::Form),
+                ("cmd1", CardType::Command),
+                ("evt1", CardType::Event),
+                ("evt2", CardType::Event),
+            ],
+            vec![("form1", "cmd1"), ("cmd1", "evt1")],
+        );
+        doc.contexts.push(ContextFrame::new(
+            "Billing".to_string(),
+            vec!["cmd1".to_string()],
+        ));
+        let expected = "An event model of 1 form, 1 command and 2 events connected by 2 flows. Contexts: Billing.";
+        let observed = doc.describe();
+        assert_eq!(expected, observed);
+        let expected = "An event model of no cards connected by 0 flows.";
+        let observed = SvgDocument::new(Theme::default()).describe();
         assert_eq!(expected, observed);
     }
+
+    #[test]
+    fn test_paginate() {
+        let mut doc = timeline_document(
+            vec![
+                ("evt1", CardType::Event),
+                ("evt2", CardType::Event),
+                ("evt3", CardType::Event),
+                ("evt4", CardType::Event),
+            ],
+            vec![],
+        );
+        doc.slices = vec![0, 2];
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        assert_eq!(vec![0f64, 975f64, 2100f64], doc.slice_breaks);
+        assert_eq!(
+            vec![0f64, 525f64, 975f64, 1425f64, 2100f64],
+            doc.column_breaks
+        );
+        // pages break between slices even where a column would fit
+        let expected = vec![(0f64, 975f64), (975f64, 2100f64)];
+        let observed = doc.paginate(1500f64);
+        assert_eq!(expected, observed);
+        // a slice wider than the page is split between its columns
+        let expected = vec![
+            (0f64, 525f64),
+            (525f64, 975f64),
+            (975f64, 1425f64),
+            (1425f64, 2100f64),
+        ];
+        let observed = doc.paginate(500f64);
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_render_pages() {
+        let mut doc = timeline_document(
+            vec![("evt1", CardType::Event), ("evt2", CardType::Event)],
+            vec![],
+        );
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        let pages = doc.render_pages(400f64).unwrap();
+        assert_eq!(pages.len(), 2);
+        let xml = roxmltree::Document::parse(&pages[1]).unwrap();
+        let root = xml.root_element();
+        assert_eq!(root.attribute("viewBox"), Some("477 0 723 1350"));
+        let labels = xml
+            .descendants()
+            .filter(|n| {
+                n.has_tag_name("text")
+                    && n.parent().and_then(|p| p.attribute("id")) == Some("lane_labels")
+            })
+            .filter_map(|n| n.text())
+            .collect::<Vec<&str>>();
+        assert_eq!(labels, vec!["Forms & Jobs", "Commands & Views", "Events"]);
+    }
+
+    #[test]
+    fn test_invalid_page_width() {
+        let mut doc = timeline_document(vec![("evt1", CardType::Event)], vec![]);
+        doc.set_dimensions(&SvgConfig::default()).unwrap();
+        for value in [0f64, -5f64, f64::NAN, f64::INFINITY] {
+            let observed = doc.render_pages(value).unwrap_err();
+            let expected = format!("page width must be a positive number, not {}", value);
+            assert_eq!(expected, observed);
+        }
+    }
 }
@@ -1,8 +1,13 @@
 mod arrow;
 mod card;
+mod context;
 mod document;
+mod route;
 mod swimlane;
+mod theme;
+pub mod xml;
 pub use arrow::*;
 pub use card::*;
-pub use document::{SvgConfig, SvgDocument};
-pub use swimlane::Swimlane;
+pub use context::ContextFrame;
+pub use document::{row, shares_column, SvgConfig, SvgDocument};
+pub use theme::{CardStyle, Theme};
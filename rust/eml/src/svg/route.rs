@@ -0,0 +1,458 @@
+use super::card::{Card, Point};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+impl Side {
+    // unit vector pointing away from the card
+    pub fn normal(&self) -> (f64, f64) {
+        match self {
+            Side::Top => (0f64, -1f64),
+            Side::Right => (1f64, 0f64),
+            Side::Bottom => (0f64, 1f64),
+            Side::Left => (-1f64, 0f64),
+        }
+    }
+
+    fn direction(&self) -> usize {
+        match self {
+            Side::Top => 0,
+            Side::Right => 1,
+            Side::Bottom => 2,
+            Side::Left => 3,
+        }
+    }
+
+    fn opposite(&self) -> Side {
+        match self {
+            Side::Top => Side::Bottom,
+            Side::Right => Side::Left,
+            Side::Bottom => Side::Top,
+            Side::Left => Side::Right,
+        }
+    }
+}
+
+fn offset(point: &Point, side: &Side, distance: f64) -> Point {
+    let (dx, dy) = side.normal();
+    Point {
+        x: point.x + dx * distance,
+        y: point.y + dy * distance,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rect {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Rect {
+    pub fn around(card: &Card, margin: f64) -> Self {
+        Rect {
+            left: card.origin.x - margin,
+            top: card.origin.y - margin,
+            right: card.origin.x + card.width + margin,
+            bottom: card.origin.y + card.height + margin,
+        }
+    }
+
+    #[cfg(test)]
+    fn contains(&self, p: &Point) -> bool {
+        self.left < p.x && p.x < self.right && self.top < p.y && p.y < self.bottom
+    }
+
+    // whether an axis aligned segment passes through the interior
+    #[cfg(test)]
+    fn crosses(&self, a: &Point, b: &Point) -> bool {
+        if a.y == b.y {
+            self.top < a.y
+                && a.y < self.bottom
+                && a.x.max(b.x) > self.left
+                && a.x.min(b.x) < self.right
+        } else {
+            self.left < a.x
+                && a.x < self.right
+                && a.y.max(b.y) > self.top
+                && a.y.min(b.y) < self.bottom
+        }
+    }
+}
+
+// the endpoint of an arrow on the outline of a card
+#[derive(Clone, Debug)]
+pub struct Port {
+    pub point: Point,
+    pub side: Side,
+}
+
+fn sorted_unique(mut values: Vec<f64>) -> Vec<f64> {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    values.dedup();
+    values
+}
+
+fn bends(from: usize, to: usize) -> u64 {
+    match (from + 4 - to) % 4 {
+        0 => 0,
+        2 => 2,
+        _ => 1,
+    }
+}
+
+// orthogonal connector routing on the grid of obstacle outlines
+pub struct Router {
+    pub obstacles: Vec<Rect>,
+    pub clearance: f64,
+    pub arrowhead: f64,
+}
+
+impl Router {
+    pub fn new(cards: &[Card], clearance: f64, arrowhead: f64) -> Self {
+        Router {
+            obstacles: cards.iter().map(|c| Rect::around(c, clearance)).collect(),
+            clearance,
+            arrowhead,
+        }
+    }
+
+    // the polyline from the start port to the tip of the arrow, which stops
+    // short of the end port to leave room for the arrowhead
+    pub fn route(&mut self, start: &Port, end: &Port) -> Vec<Point> {
+        let exit = offset(&start.point, &start.side, self.clearance);
+        let entry = offset(&end.point, &end.side, self.clearance);
+        let tip = offset(&end.point, &end.side, self.arrowhead);
+        let arrival = end.side.opposite();
+        let middle = self
+            .shortest_path(&exit, &start.side, &entry, &arrival)
+            .unwrap_or_else(|| match start.side {
+                Side::Top | Side::Bottom => vec![
+                    exit.clone(),
+                    Point {
+                        x: exit.x,
+                        y: entry.y,
+                    },
+                    entry.clone(),
+                ],
+                Side::Left | Side::Right => vec![
+                    exit.clone(),
+                    Point {
+                        x: entry.x,
+                        y: exit.y,
+                    },
+                    entry.clone(),
+                ],
+            });
+        // later arrows keep clear of this arrowhead
+        let half = self.arrowhead / 2f64;
+        self.obstacles.push(Rect {
+            left: end.point.x.min(entry.x) - half,
+            top: end.point.y.min(entry.y) - half,
+            right: end.point.x.max(entry.x) + half,
+            bottom: end.point.y.max(entry.y) + half,
+        });
+        let mut points = vec![start.point.clone()];
+        points.extend(middle);
+        points.push(tip);
+        simplify(points)
+    }
+
+    fn shortest_path(
+        &self,
+        from: &Point,
+        heading: &Side,
+        to: &Point,
+        arrival: &Side,
+    ) -> Option<Vec<Point>> {
+        let grid = Grid::new(&self.obstacles, from, to);
+        let (source, target) = (grid.index(from)?, grid.index(to)?);
+        let open = |node: usize| node == source || node == target || !grid.blocked[node];
+        // a bend costs as much as a detour of this length
+        let penalty = (self.clearance * 4f64 * 100f64) as u64;
+        let mut best: HashMap<(usize, usize), u64> = HashMap::new();
+        let mut previous: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let start = (source, heading.direction());
+        best.insert(start, 0);
+        queue.push(Reverse((0u64, start)));
+        let mut reached: Option<(u64, (usize, usize))> = None;
+        while let Some(Reverse((cost, state))) = queue.pop() {
+            // nothing left in the queue can arrive any cheaper
+            if reached.is_some_and(|(c, _)| cost >= c) {
+                break;
+            }
+            if best.get(&state).is_some_and(|c| *c < cost) {
+                continue;
+            }
+            let (node, direction) = state;
+            if node == target {
+                // turning at the last moment squeezes the arrowhead
+                let total = cost + bends(direction, arrival.direction()) * penalty * 2;
+                if reached.is_none_or(|(c, _)| total < c) {
+                    reached = Some((total, state));
+                }
+                continue;
+            }
+            let width = grid.xs.len();
+            let (i, j) = (node % width, node / width);
+            let neighbours = [
+                (j > 0 && !grid.vertical[node - width]).then(|| (node - width, 0)),
+                (i + 1 < width && !grid.horizontal[node]).then(|| (node + 1, 1)),
+                (j + 1 < grid.ys.len() && !grid.vertical[node]).then(|| (node + width, 2)),
+                (i > 0 && !grid.horizontal[node - 1]).then(|| (node - 1, 3)),
+            ];
+            for (next, next_direction) in neighbours.into_iter().flatten() {
+                // no u-turns
+                if bends(direction, next_direction) == 2 || !open(next) {
+                    continue;
+                }
+                let (a, b) = (grid.point(node), grid.point(next));
+                let length = ((a.x - b.x).abs() + (a.y - b.y).abs()) * 100f64;
+                let next_cost = cost + length as u64 + bends(direction, next_direction) * penalty;
+                let next_state = (next, next_direction);
+                if best.get(&next_state).is_none_or(|c| next_cost < *c) {
+                    best.insert(next_state, next_cost);
+                    previous.insert(next_state, state);
+                    queue.push(Reverse((next_cost, next_state)));
+                }
+            }
+        }
+        let (_, mut state) = reached?;
+        let mut path = vec![grid.point(state.0)];
+        while let Some(prev) = previous.get(&state) {
+            path.push(grid.point(prev.0));
+            state = *prev;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+// the grid of obstacle outlines, with the nodes inside an obstacle and the
+// segments through one marked up front rather than checked at every step
+struct Grid {
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    blocked: Vec<bool>,
+    // the segment from a node to its right or lower neighbour
+    horizontal: Vec<bool>,
+    vertical: Vec<bool>,
+}
+
+// the indices of the sorted values strictly between low and high
+fn inside(values: &[f64], low: f64, high: f64) -> std::ops::Range<usize> {
+    values.partition_point(|v| *v <= low)..values.partition_point(|v| *v < high)
+}
+
+impl Grid {
+    fn new(obstacles: &[Rect], from: &Point, to: &Point) -> Self {
+        let mut xs = vec![from.x, to.x];
+        let mut ys = vec![from.y, to.y];
+        for r in obstacles.iter() {
+            xs.extend([r.left, r.right]);
+            ys.extend([r.top, r.bottom]);
+        }
+        let (xs, ys) = (sorted_unique(xs), sorted_unique(ys));
+        let size = xs.len() * ys.len();
+        let mut grid = Grid {
+            blocked: vec![false; size],
+            horizontal: vec![false; size],
+            vertical: vec![false; size],
+            xs,
+            ys,
+        };
+        let width = grid.xs.len();
+        for r in obstacles.iter() {
+            let columns = inside(&grid.xs, r.left, r.right);
+            let rows = inside(&grid.ys, r.top, r.bottom);
+            for j in rows.clone() {
+                for i in columns.clone() {
+                    grid.blocked[j * width + i] = true;
+                }
+                // segments that start left of the obstacle and end inside it
+                for i in columns.start.saturating_sub(1)..columns.end.min(width - 1) {
+                    grid.horizontal[j * width + i] = true;
+                }
+            }
+            for j in rows.start.saturating_sub(1)..rows.end.min(grid.ys.len() - 1) {
+                for i in columns.clone() {
+                    grid.vertical[j * width + i] = true;
+                }
+            }
+        }
+        grid
+    }
+
+    fn index(&self, p: &Point) -> Option<usize> {
+        let position =
+            |values: &[f64], v: f64| values.binary_search_by(|x| x.partial_cmp(&v).unwrap()).ok();
+        Some(position(&self.ys, p.y)? * self.xs.len() + position(&self.xs, p.x)?)
+    }
+
+    fn point(&self, node: usize) -> Point {
+        Point {
+            x: self.xs[node % self.xs.len()],
+            y: self.ys[node / self.xs.len()],
+        }
+    }
+}
+
+// drop repeated and collinear points
+fn simplify(points: Vec<Point>) -> Vec<Point> {
+    let mut simplified: Vec<Point> = Vec::with_capacity(points.len());
+    for p in points {
+        if let Some(last) = simplified.last() {
+            if last.x == p.x && last.y == p.y {
+                continue;
+            }
+        }
+        if simplified.len() >= 2 {
+            let (a, b) = (
+                &simplified[simplified.len() - 2],
+                &simplified[simplified.len() - 1],
+            );
+            if (a.x == b.x && b.x == p.x) || (a.y == b.y && b.y == p.y) {
+                simplified.pop();
+            }
+        }
+        simplified.push(p);
+    }
+    simplified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::CardType;
+
+    fn card(id: &str, x: f64, y: f64) -> Card {
+        let mut card = Card::new(id.to_string(), CardType::Event, vec![]);
+        card.set_origin(x, y);
+        card.set_dimensions(100f64, 100f64, 12.5);
+        card
+    }
+
+    #[test]
+    fn test_simplify() {
+        let points = vec![
+            Point { x: 0f64, y: 0f64 },
+            Point { x: 0f64, y: 0f64 },
+            Point { x: 5f64, y: 0f64 },
+            Point { x: 10f64, y: 0f64 },
+            Point { x: 10f64, y: 10f64 },
+        ];
+        let observed = simplify(points)
+            .iter()
+            .map(|p| (p.x, p.y))
+            .collect::<Vec<(f64, f64)>>();
+        assert_eq!(observed, vec![(0f64, 0f64), (10f64, 0f64), (10f64, 10f64)]);
+    }
+
+    #[test]
+    fn test_grid() {
+        let cards = vec![
+            card("a", 0f64, 0f64),
+            card("b", 150f64, 40f64),
+            card("c", 60f64, 200f64),
+        ];
+        let router = Router::new(&cards, 30f64, 15f64);
+        let (from, to) = (
+            Point {
+                x: -50f64,
+                y: 75f64,
+            },
+            Point { x: 300f64, y: 5f64 },
+        );
+        let grid = Grid::new(&router.obstacles, &from, &to);
+        let width = grid.xs.len();
+        let crossed = |a: &Point, b: &Point| router.obstacles.iter().any(|r| r.crosses(a, b));
+        for node in 0..grid.blocked.len() {
+            let p = grid.point(node);
+            let inside = router.obstacles.iter().any(|r| r.contains(&p));
+            assert_eq!(grid.blocked[node], inside);
+            if node % width + 1 < width {
+                assert_eq!(grid.horizontal[node], crossed(&p, &grid.point(node + 1)));
+            }
+            if node + width < grid.blocked.len() {
+                assert_eq!(grid.vertical[node], crossed(&p, &grid.point(node + width)));
+            }
+        }
+        assert_eq!(grid.point(grid.index(&from).unwrap()).x, from.x);
+    }
+
+    #[test]
+    fn test_route_straight() {
+        let cards = vec![card("a", 0f64, 0f64), card("b", 300f64, 0f64)];
+        let mut router = Router::new(&cards, 30f64, 15f64);
+        let start = Port {
+            point: cards[0].side_anchor(&Side::Right, 0.5),
+            side: Side::Right,
+        };
+        let end = Port {
+            point: cards[1].side_anchor(&Side::Left, 0.5),
+            side: Side::Left,
+        };
+        let observed = router
+            .route(&start, &end)
+            .iter()
+            .map(|p| (p.x, p.y))
+            .collect::<Vec<(f64, f64)>>();
+        assert_eq!(observed, vec![(100f64, 50f64), (285f64, 50f64)]);
+    }
+
+    #[test]
+    fn test_route_around_card() {
+        let cards = vec![
+            card("a", 0f64, 0f64),
+            card("b", 200f64, 0f64),
+            card("c", 400f64, 0f64),
+        ];
+        let mut router = Router::new(&cards, 30f64, 15f64);
+        let start = Port {
+            point: cards[0].side_anchor(&Side::Right, 0.5),
+            side: Side::Right,
+        };
+        let end = Port {
+            point: cards[2].side_anchor(&Side::Left, 0.5),
+            side: Side::Left,
+        };
+        let observed = router.route(&start, &end);
+        assert!(observed.len() > 2);
+        for pair in observed.windows(2) {
+            let r = Rect::around(&cards[1], 0f64);
+            assert!(!r.crosses(&pair[0], &pair[1]));
+        }
+        let last = observed.last().unwrap();
+        assert_eq!((last.x, last.y), (385f64, 50f64));
+    }
+
+    #[test]
+    fn test_route_avoids_arrowheads() {
+        let cards = vec![card("a", 0f64, 0f64), card("b", 300f64, 0f64)];
+        let mut router = Router::new(&cards, 30f64, 15f64);
+        let end = Port {
+            point: cards[1].side_anchor(&Side::Top, 0.5),
+            side: Side::Top,
+        };
+        let start = Port {
+            point: cards[0].side_anchor(&Side::Top, 0.5),
+            side: Side::Top,
+        };
+        router.route(&start, &end);
+        assert_eq!(router.obstacles.len(), 3);
+        let arrowhead = router.obstacles[2].clone();
+        assert!(arrowhead.contains(&Point {
+            x: 350f64,
+            y: -10f64
+        }));
+    }
+}
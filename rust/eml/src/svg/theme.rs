@@ -0,0 +1,307 @@
+use super::card::CardType;
+use super::xml::escape;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// monospace glyphs are about this fraction of the font size wide
+const CHAR_ASPECT: f64 = 0.6;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CardStyle {
+    pub fill: String,
+    pub stroke: String,
+    pub text: String,
+}
+
+impl CardStyle {
+    fn new(fill: &str, stroke: &str, text: &str) -> Self {
+        CardStyle {
+            fill: fill.to_string(),
+            stroke: stroke.to_string(),
+            text: text.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineStyle {
+    pub stroke: String,
+    pub stroke_width: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dasharray: Option<String>,
+}
+
+impl LineStyle {
+    fn new(stroke: &str, stroke_width: f64, dasharray: Option<&str>) -> Self {
+        LineStyle {
+            stroke: stroke.to_string(),
+            stroke_width,
+            dasharray: dasharray.map(|d| d.to_string()),
+        }
+    }
+
+    // stroke attributes for an svg element
+    pub fn attributes(&self) -> String {
+        let dash = match &self.dasharray {
+            Some(d) => format!(" stroke-dasharray='{}'", escape(d)),
+            None => String::new(),
+        };
+        format!(
+            "stroke='{}' stroke-width='{}'{}",
+            escape(&self.stroke),
+            self.stroke_width,
+            dash
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    pub font_family: String,
+    pub font_size: f64,
+    pub title_font_size: f64,
+    pub card_stroke_width: f64,
+    pub form: CardStyle,
+    pub job: CardStyle,
+    pub command: CardStyle,
+    pub event: CardStyle,
+    pub view: CardStyle,
+    pub arrow: LineStyle,
+    pub lane: LineStyle,
+    pub context: LineStyle,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            background: None,
+            font_family: "monospace".to_string(),
+            font_size: 16f64,
+            title_font_size: 19.2,
+            card_stroke_width: 2f64,
+            form: CardStyle::new("#ffffff", "black", "black"),
+            job: CardStyle::new("#ffffff", "black", "black"),
+            command: CardStyle::new("#60b3f7", "black", "black"),
+            event: CardStyle::new("#f7a660", "black", "black"),
+            view: CardStyle::new("#60f765", "black", "black"),
+            arrow: LineStyle::new("black", 2f64, None),
+            lane: LineStyle::new("black", 3f64, None),
+            context: LineStyle::new("black", 2f64, Some("10 5")),
+        }
+    }
+}
+
+impl Theme {
+    // card types stay distinguishable in greyscale print
+    pub fn monochrome() -> Self {
+        Theme {
+            form: CardStyle::new("#ffffff", "black", "black"),
+            job: CardStyle::new("#ffffff", "black", "black"),
+            command: CardStyle::new("#d9d9d9", "black", "black"),
+            event: CardStyle::new("#a6a6a6", "black", "black"),
+            view: CardStyle::new("#f0f0f0", "black", "black"),
+            lane: LineStyle::new("black", 3f64, Some("12 6")),
+            ..Theme::default()
+        }
+    }
+
+    pub fn dark() -> Self {
+        Theme {
+            background: Some("#1e1e1e".to_string()),
+            form: CardStyle::new("#2d2d2d", "#d4d4d4", "#d4d4d4"),
+            job: CardStyle::new("#2d2d2d", "#d4d4d4", "#d4d4d4"),
+            command: CardStyle::new("#1f4e79", "#9cdcfe", "#ffffff"),
+            event: CardStyle::new("#7a4419", "#f7a660", "#ffffff"),
+            view: CardStyle::new("#1e5c28", "#60f765", "#ffffff"),
+            arrow: LineStyle::new("#d4d4d4", 2f64, None),
+            lane: LineStyle::new("#808080", 3f64, None),
+            context: LineStyle::new("#d4d4d4", 2f64, Some("10 5")),
+            ..Theme::default()
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Theme::default()),
+            "monochrome" => Some(Theme::monochrome()),
+            "dark" => Some(Theme::dark()),
+            _ => None,
+        }
+    }
+
+    // a built-in theme name, or a toml or json file. files only need the
+    // values they change and can start from a built-in with `base = "dark"`
+    pub fn load(spec: &str) -> Result<Self, String> {
+        if let Some(theme) = Theme::builtin(spec) {
+            return Ok(theme);
+        }
+        let text = fs::read_to_string(spec).map_err(|e| format!("theme {}: {}", spec, e))?;
+        let is_json = Path::new(spec)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let overrides = if is_json {
+            let value: serde_json::Value =
+                serde_json::from_str(&text).map_err(|e| e.to_string())?;
+            toml::Value::try_from(value).map_err(|e| e.to_string())?
+        } else {
+            toml::from_str::<toml::Value>(&text).map_err(|e| e.to_string())?
+        };
+        Theme::from_overrides(overrides)
+    }
+
+    pub fn from_overrides(mut overrides: toml::Value) -> Result<Self, String> {
+        let base = match overrides.as_table_mut().and_then(|t| t.remove("base")) {
+            Some(toml::Value::String(name)) => {
+                Theme::builtin(&name).ok_or(format!("unknown base theme: {}", name))?
+            }
+            Some(_) => return Err("base theme must be a name".to_string()),
+            None => Theme::default(),
+        };
+        let mut merged = toml::Value::try_from(base).map_err(|e| e.to_string())?;
+        merge(&mut merged, overrides);
+        let theme: Theme = merged
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+        // text is measured from the font sizes
+        for (name, size) in [
+            ("font_size", theme.font_size),
+            ("title_font_size", theme.title_font_size),
+        ] {
+            if !(size.is_finite() && size > 0f64) {
+                return Err(format!(
+                    "theme {} must be a positive number, not {}",
+                    name, size
+                ));
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn card_style(&self, card_type: &CardType) -> &CardStyle {
+        match card_type {
+            CardType::Form => &self.form,
+            CardType::Job => &self.job,
+            CardType::Command => &self.command,
+            CardType::Event => &self.event,
+            CardType::View => &self.view,
+        }
+    }
+
+    pub fn char_width(&self) -> f64 {
+        self.font_size * CHAR_ASPECT
+    }
+
+    pub fn line_height(&self) -> f64 {
+        self.font_size
+    }
+
+    pub fn title_scale(&self) -> f64 {
+        self.title_font_size / self.font_size
+    }
+}
+
+fn merge(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_overrides() {
+        let overrides = toml::from_str::<toml::Value>(
+            r##"
+            font_size = 12.0
+
+            [command]
+            text = "#ffffff"
+            "##,
+        )
+        .unwrap();
+        let theme = Theme::from_overrides(overrides).unwrap();
+        assert_eq!(theme.font_size, 12f64);
+        assert_eq!(theme.command.text, "#ffffff");
+        assert_eq!(theme.command.fill, "#60b3f7");
+        assert_eq!(theme.event, Theme::default().event);
+    }
+
+    #[test]
+    fn test_base_theme() {
+        let overrides = toml::from_str::<toml::Value>(
+            r##"
+            base = "dark"
+            [lane]
+            dasharray = "4 4"
+            "##,
+        )
+        .unwrap();
+        let theme = Theme::from_overrides(overrides).unwrap();
+        assert_eq!(theme.background, Theme::dark().background);
+        assert_eq!(theme.lane.stroke, Theme::dark().lane.stroke);
+        assert_eq!(theme.lane.dasharray, Some("4 4".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_base_theme() {
+        let overrides = toml::from_str::<toml::Value>("base = \"neon\"").unwrap();
+        assert!(Theme::from_overrides(overrides).is_err());
+    }
+
+    #[test]
+    fn test_unknown_theme_key() {
+        for text in ["fontsize = 20.0", "[lane]\nstrok = \"red\""] {
+            let overrides = toml::from_str::<toml::Value>(text).unwrap();
+            let observed = Theme::from_overrides(overrides).unwrap_err();
+            assert!(observed.contains("unknown field"), "{}", observed);
+        }
+    }
+
+    #[test]
+    fn test_invalid_font_size() {
+        let overrides = toml::from_str::<toml::Value>("font_size = 0.0").unwrap();
+        let observed = Theme::from_overrides(overrides).unwrap_err();
+        assert_eq!(observed, "theme font_size must be a positive number, not 0");
+        let overrides = toml::from_str::<toml::Value>("title_font_size = -1.0").unwrap();
+        assert!(Theme::from_overrides(overrides).is_err());
+    }
+
+    #[test]
+    fn test_json_overrides() {
+        let value: serde_json::Value = serde_json::from_str(
+            r##"{"view": {"fill": "#00ff00"}, "arrow": {"stroke_width": 1}}"##,
+        )
+        .unwrap();
+        let theme = Theme::from_overrides(toml::Value::try_from(value).unwrap()).unwrap();
+        assert_eq!(theme.view.fill, "#00ff00");
+        assert_eq!(theme.arrow.stroke_width, 1f64);
+    }
+
+    #[test]
+    fn test_builtin_round_trip() {
+        for name in ["default", "monochrome", "dark"] {
+            let theme = Theme::builtin(name).unwrap();
+            let text = toml::to_string(&theme).unwrap();
+            let overrides = toml::from_str::<toml::Value>(&text).unwrap();
+            assert_eq!(Theme::from_overrides(overrides).unwrap(), theme);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::utils::unique_id;
+
+// escape user text for use in element content and quoted attributes
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // control characters are not allowed in xml 1.0 at all
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => escaped.push('\u{fffd}'),
+            // nor are these two noncharacters
+            '\u{fffe}' | '\u{ffff}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn is_id_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+// map an expression id onto a valid xml id, replacing anything else with '_'
+pub fn sanitize_id(id: &str) -> String {
+    let mut sanitized = id
+        .chars()
+        .map(|c| if is_id_char(c) { c } else { '_' })
+        .collect::<String>();
+    if !sanitized.starts_with(is_id_start) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+// distinct xml ids for a list of ids. the prefix keeps them apart from the
+// document's own ids, and ids that sanitise to one already given out are
+// numbered
+pub fn xml_ids<'a>(prefix: &str, ids: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let sanitized = ids
+        .into_iter()
+        .map(|id| sanitize_id(&format!("{}{}", prefix, id)))
+        .collect::<Vec<String>>();
+    let mut given: Vec<String> = Vec::with_capacity(sanitized.len());
+    for id in sanitized.iter() {
+        let xml_id = match given.contains(id) {
+            true => {
+                let taken = sanitized
+                    .iter()
+                    .chain(given.iter())
+                    .map(String::as_str)
+                    .collect::<Vec<&str>>();
+                unique_id(id.clone(), &taken)
+            }
+            false => id.clone(),
+        };
+        given.push(xml_id);
+    }
+    given
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        let observed = escape("a < b & \"c\" > 'd'");
+        let expected = "a &lt; b &amp; &quot;c&quot; &gt; &apos;d&apos;";
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_escape_control() {
+        assert_eq!(escape("a\u{0}b\u{1b}c\td"), "a\u{fffd}b\u{fffd}c\td");
+    }
+
+    #[test]
+    fn test_sanitize_id() {
+        assert_eq!(sanitize_id("Billing.AccountAdded"), "Billing.AccountAdded");
+        assert_eq!(sanitize_id("a b'><c"), "a_b___c");
+        assert_eq!(sanitize_id("1st"), "_1st");
+        assert_eq!(sanitize_id("-x"), "_-x");
+        assert_eq!(sanitize_id(""), "_");
+    }
+
+    #[test]
+    fn test_xml_ids() {
+        let observed = xml_ids("card-", ["triangle", "a b", "a_b", "a_b-2", "a b", "1st"]);
+        let expected = vec![
+            "card-triangle",
+            "card-a_b",
+            "card-a_b-3",
+            "card-a_b-2",
+            "card-a_b-4",
+            "card-1st",
+        ];
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_escape_noncharacters() {
+        assert_eq!(escape("a\u{fffe}b\u{ffff}c"), "abc");
+    }
+}
@@ -0,0 +1,260 @@
+use super::cst::{Node, SyntaxKind};
+use super::eventmodel::ExpressionId;
+use std::ops::Range;
+
+// where the ids of a model are defined and used, by their spans in the
+// source. references resolve the way the svg does: a bare id inside a
+// context prefers that context's own cards declared above it
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    // qualified by the context for cards in one
+    pub id: ExpressionId,
+    pub keyword: String,
+    pub context: Option<ExpressionId>,
+    // the id as written, and the whole expression
+    pub name: Range<usize>,
+    pub span: Range<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    // the resolved id
+    pub id: ExpressionId,
+    pub context: Option<ExpressionId>,
+    // the last part of the reference, and all of it
+    pub name: Range<usize>,
+    pub span: Range<usize>,
+    // whether the card is declared above the reference
+    pub declared: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Symbols {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    // the steps of each flow as written, and the flow
+    pub flows: Vec<(String, Range<usize>)>,
+}
+
+fn contains(span: &Range<usize>, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+impl Symbols {
+    pub fn new(file: &Node) -> Self {
+        let mut symbols = Symbols::default();
+        symbols.add(None, file);
+        symbols
+    }
+
+    pub fn cards(&self) -> impl Iterator<Item = &Definition> {
+        self.definitions.iter().filter(|d| d.keyword != "context")
+    }
+
+    // an undeclared reference keeps its id so that it can be reported
+    fn resolve(&self, context: Option<&ExpressionId>, id: ExpressionId) -> ExpressionId {
+        let declared = |id: &str| self.cards().any(|d| d.id.0 == id);
+        id.resolve(context, declared).unwrap_or(id)
+    }
+
+    fn reference(&mut self, context: Option<&ExpressionId>, node: &Node) {
+        let id = self.resolve(context, ExpressionId(node.text()));
+        let declared = self.cards().any(|d| d.id == id);
+        let name = node.tokens().last().map_or(node.span(), |t| t.span());
+        self.references.push(Reference {
+            id,
+            context: context.cloned(),
+            name,
+            span: node.span(),
+            declared,
+        });
+    }
+
+    fn add(&mut self, context: Option<&ExpressionId>, node: &Node) {
+        for child in node.nodes() {
+            let keyword = child.token(SyntaxKind::Keyword).map(|t| t.text.clone());
+            match (child.kind, child.token(SyntaxKind::Ident)) {
+                (SyntaxKind::Flow, _) => {
+                    let steps = child.nodes().map(|n| n.text()).collect::<Vec<String>>();
+                    self.flows.push((steps.join(" => "), child.span()));
+                    for step in child.nodes() {
+                        self.reference(context, step);
+                    }
+                }
+                (_, Some(name)) => {
+                    let id = ExpressionId(name.text.clone());
+                    self.definitions.push(Definition {
+                        id: match context {
+                            Some(ctx) => id.qualified(ctx),
+                            None => id.clone(),
+                        },
+                        keyword: keyword.unwrap_or_default(),
+                        context: context.cloned(),
+                        name: name.span(),
+                        span: child.span(),
+                    });
+                    match child.kind {
+                        SyntaxKind::Context => self.add(Some(&id), child),
+                        _ => {
+                            let used = child
+                                .node(SyntaxKind::Use)
+                                .and_then(|body| body.node(SyntaxKind::Reference));
+                            if let Some(used) = used {
+                                self.reference(context, used);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the card defined with an id
+    pub fn definition(&self, id: &ExpressionId) -> Option<&Definition> {
+        self.cards().find(|d| &d.id == id)
+    }
+
+    pub fn references_to<'a>(
+        &'a self,
+        id: &'a ExpressionId,
+    ) -> impl Iterator<Item = &'a Reference> + 'a {
+        self.references.iter().filter(move |r| &r.id == id)
+    }
+
+    // the card whose id or a reference to it is at an offset
+    pub fn at(&self, offset: usize) -> Option<&ExpressionId> {
+        let reference = self.references.iter().find(|r| contains(&r.span, offset));
+        let definition = self.cards().find(|d| contains(&d.name, offset));
+        reference.map(|r| &r.id).or(definition.map(|d| &d.id))
+    }
+
+    // duplicate ids, nested contexts, and references the svg could not draw
+    pub fn problems(&self) -> Vec<(Range<usize>, String)> {
+        let mut problems = Vec::new();
+        for (i, card) in self.cards().enumerate() {
+            if self.cards().take(i).any(|d| d.id == card.id) {
+                problems.push((card.name.clone(), format!("duplicate id {}", card.id.0)));
+            }
+        }
+        for context in self.definitions.iter() {
+            if context.keyword == "context" && context.context.is_some() {
+                let message = "contexts cannot be nested".to_string();
+                problems.push((context.name.clone(), message));
+            }
+        }
+        for reference in self.references.iter().filter(|r| !r.declared) {
+            let message = match self.definition(&reference.id) {
+                Some(_) => format!("{} is used before it is defined", reference.id.0),
+                None => format!("unknown id {}", reference.id.0),
+            };
+            problems.push((reference.span.clone(), message));
+        }
+        problems.sort_by_key(|(span, _)| span.start);
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cst::parse;
+    use indoc::indoc;
+
+    const MODEL: &str = indoc! {"
+        # eml: 0.0.1
+        form AddForm { title: milk }
+        command Add { use AddForm }
+        context Lists {
+          command Add { use AddForm }
+          event Added { use Add }
+          flow { Add => Added }
+        }
+        flow { AddForm => Lists.Add => Lists.Added => Missing }
+    "};
+
+    fn span(text: &str, n: usize) -> Range<usize> {
+        let start = MODEL.match_indices(text).nth(n).unwrap().0;
+        start..start + text.len()
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols = Symbols::new(&parse(MODEL));
+        let ids = symbols
+            .definitions
+            .iter()
+            .map(|d| (d.keyword.as_str(), d.id.0.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        let expected = vec![
+            ("form", "AddForm"),
+            ("command", "Add"),
+            ("context", "Lists"),
+            ("command", "Lists.Add"),
+            ("event", "Lists.Added"),
+        ];
+        assert_eq!(expected, ids);
+        let references = symbols
+            .references
+            .iter()
+            .map(|r| r.id.0.as_str())
+            .collect::<Vec<&str>>();
+        let expected = vec![
+            "AddForm",
+            "AddForm",
+            "Lists.Add",
+            "Lists.Add",
+            "Lists.Added",
+            "AddForm",
+            "Lists.Add",
+            "Lists.Added",
+            "Missing",
+        ];
+        assert_eq!(expected, references);
+        assert_eq!(symbols.flows[0].0, "Add => Added");
+    }
+
+    #[test]
+    fn test_symbols_at() {
+        let symbols = Symbols::new(&parse(MODEL));
+        let qualified = span("Lists.Add ", 0);
+        assert_eq!(symbols.at(qualified.start).unwrap().0, "Lists.Add");
+        assert_eq!(symbols.at(span("Add ", 0).start).unwrap().0, "Add");
+        assert_eq!(symbols.at(0), None);
+        let id = ExpressionId("Lists.Add".to_string());
+        let names = symbols
+            .references_to(&id)
+            .map(|r| &MODEL[r.name.clone()])
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["Add", "Add", "Add"]);
+    }
+
+    #[test]
+    fn test_problems() {
+        let input = indoc! {"
+            # eml: 0.0.1
+            flow { Later => Nowhere }
+            command Later {}
+            command Later {}
+            context Outer {
+              context Inner {}
+            }
+        "};
+        let symbols = Symbols::new(&parse(input));
+        let later = input.find("Later").unwrap();
+        let nowhere = input.find("Nowhere").unwrap();
+        let duplicate = input.rfind("Later").unwrap();
+        let inner = input.find("Inner").unwrap();
+        let expected = vec![
+            (
+                later..later + 5,
+                "Later is used before it is defined".to_string(),
+            ),
+            (nowhere..nowhere + 7, "unknown id Nowhere".to_string()),
+            (duplicate..duplicate + 5, "duplicate id Later".to_string()),
+            (inner..inner + 5, "contexts cannot be nested".to_string()),
+        ];
+        assert_eq!(expected, symbols.problems());
+    }
+}
@@ -1,5 +1,53 @@
-use uuid::Uuid;
+// ids are derived from the model so unchanged input renders identically
 
-pub fn newid() -> String {
-    Uuid::new_v4().to_string()
+pub fn flow_id<'a>(ids: impl IntoIterator<Item = &'a str>) -> String {
+    std::iter::once("flow")
+        .chain(ids)
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+// the first id in taken order that is not already used, counting up from 2
+pub fn unique_id(id: String, taken: &[&str]) -> String {
+    if !taken.contains(&id.as_str()) {
+        return id;
+    }
+    (2..)
+        .map(|n| format!("{}-{}", id, n))
+        .find(|candidate| !taken.contains(&candidate.as_str()))
+        .unwrap()
+}
+
+// 64 bit fnv-1a, stable across platforms and compiler versions
+pub fn content_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flow_id() {
+        let expected = "flow-CustomerForm-AddCustomer";
+        let observed = flow_id(["CustomerForm", "AddCustomer"]);
+        assert_eq!(expected, observed);
+    }
+
+    #[test]
+    fn test_unique_id() {
+        let taken = vec!["flow-a-b", "flow-a-b-2"];
+        assert_eq!(unique_id("flow-a-b".to_string(), &taken), "flow-a-b-3");
+        assert_eq!(unique_id("flow-b-c".to_string(), &taken), "flow-b-c");
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_eq!(content_hash("a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash("ab"), content_hash("ba"));
+    }
 }